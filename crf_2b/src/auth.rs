@@ -0,0 +1,176 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use actix_web::rt;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use libfj::robocraft2::{FactoryAPI, FactoryError, ITokenProvider, PortalTokenProvider, SearchPayload, SearchResponse};
+
+use crate::config::AuthConfig;
+use crate::store;
+
+pub enum UpstreamError {
+    /// Not logged in to the portal (yet)
+    Unavailable,
+    Factory(FactoryError),
+}
+
+impl From<FactoryError> for UpstreamError {
+    fn from(e: FactoryError) -> Self {
+        Self::Factory(e)
+    }
+}
+
+struct Session {
+    api: Arc<FactoryAPI>,
+    generation: u64,
+    /// When the portal token expires (unix seconds), if it says
+    expires: Option<i64>,
+}
+
+/// The `exp` claim of a JWT, in unix seconds. The signature isn't checked; this is
+/// only for knowing when to log in again.
+fn token_expiry(token: &str) -> Option<i64> {
+    let payload = token.split('.').nth(1)?;
+    let json = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    serde_json::from_slice::<serde_json::Value>(&json).ok()?.get("exp")?.as_i64()
+}
+
+/// Owns the portal login and hands out a logged-in `FactoryAPI`.
+///
+/// The login happens in the background so the HTTP server can start while the
+/// portal is unreachable; until it succeeds, upstream calls fail with `UpstreamError::Unavailable`.
+pub struct TokenManager {
+    username: String,
+    password: String,
+    settings: AuthConfig,
+    session: RwLock<Option<Session>>,
+    login_lock: tokio::sync::Mutex<()>,
+}
+
+impl TokenManager {
+    pub fn new(username: String, password: String, settings: AuthConfig) -> Arc<Self> {
+        Arc::new(Self {
+            username,
+            password,
+            settings,
+            session: RwLock::new(None),
+            login_lock: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// Log in with retries, then keep refreshing the login before the token expires.
+    /// A search that's refused anyway logs in again on the spot; see [`search`](Self::search).
+    pub fn spawn_refresh(self: &Arc<Self>) {
+        let manager = self.clone();
+        rt::spawn(async move {
+            loop {
+                manager.login_with_backoff().await;
+                rt::time::sleep(manager.refresh_delay(store::now())).await;
+            }
+        });
+    }
+
+    /// How long until the current login should be refreshed: `refresh_margin_secs` before
+    /// the token expires, or `refresh_interval_secs` when its expiry isn't known
+    fn refresh_delay(&self, now: i64) -> Duration {
+        let expires = self.session.read().unwrap().as_ref().and_then(|s| s.expires);
+        let secs = match expires {
+            Some(expires) => (expires - self.settings.refresh_margin_secs as i64 - now).max(1) as u64,
+            None => self.settings.refresh_interval_secs,
+        };
+        Duration::from_secs(secs)
+    }
+
+    fn current(&self) -> Option<(Arc<FactoryAPI>, u64)> {
+        self.session.read().unwrap()
+            .as_ref()
+            .map(|s| (s.api.clone(), s.generation))
+    }
+
+    async fn login(&self) -> bool {
+        match PortalTokenProvider::with_username(&self.username, &self.password).await {
+            Ok(provider) => {
+                let expires = provider.token().await.ok().as_deref().and_then(token_expiry);
+                let mut session = self.session.write().unwrap();
+                let generation = session.as_ref().map(|s| s.generation + 1).unwrap_or(0);
+                *session = Some(Session {
+                    api: Arc::new(FactoryAPI::with_auth(Box::new(provider))),
+                    generation,
+                    expires,
+                });
+                match expires {
+                    Some(expires) => println!("Logged in to portal as {} (session {}, token expires in {}s)", self.username, generation, expires - store::now()),
+                    None => println!("Logged in to portal as {} (session {}, token expiry unknown)", self.username, generation),
+                }
+                true
+            },
+            Err(e) => {
+                println!("Portal login error: {:?}", e);
+                false
+            }
+        }
+    }
+
+    async fn login_with_backoff(&self) {
+        let mut delay = Duration::from_secs(self.settings.retry_initial_secs);
+        let max_delay = Duration::from_secs(self.settings.retry_max_secs);
+        loop {
+            {
+                let _guard = self.login_lock.lock().await;
+                if self.login().await {
+                    return;
+                }
+            }
+            println!("Retrying portal login in {}s", delay.as_secs());
+            rt::time::sleep(delay).await;
+            delay = (delay * 2).min(max_delay);
+        }
+    }
+
+    /// Log in again, unless someone else already replaced the session that failed
+    async fn relogin(&self, failed_generation: u64) -> Option<Arc<FactoryAPI>> {
+        let _guard = self.login_lock.lock().await;
+        if let Some((api, generation)) = self.current() {
+            if generation != failed_generation {
+                return Some(api);
+            }
+        }
+        if self.login().await {
+            self.current().map(|(api, _)| api)
+        } else {
+            None
+        }
+    }
+
+    pub async fn search(&self, payload: SearchPayload) -> Result<SearchResponse, UpstreamError> {
+        let (api, generation) = self.current().ok_or(UpstreamError::Unavailable)?;
+        match api.search(payload.clone()).await {
+            Err(FactoryError::ResponseCode(e, 401)) => {
+                println!("Search unauthorized ({}), logging in again", e);
+                let api = self.relogin(generation).await.ok_or(UpstreamError::Unavailable)?;
+                Ok(api.search(payload).await?)
+            },
+            result => Ok(result?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jwt(claims: &str) -> String {
+        format!("{}.{}.signature", URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256"}"#), URL_SAFE_NO_PAD.encode(claims))
+    }
+
+    #[test]
+    fn reads_token_expiry() {
+        assert_eq!(token_expiry(&jwt(r#"{"sub":"someone","exp":1700000000}"#)), Some(1700000000));
+        // padding some encoders leave on
+        assert_eq!(token_expiry(&format!("{}==", jwt(r#"{"exp":1}"#).trim_end_matches(".signature"))), Some(1));
+        assert_eq!(token_expiry(&jwt(r#"{"sub":"someone"}"#)), None);
+        assert_eq!(token_expiry("not a token"), None);
+        assert_eq!(token_expiry("a.!!!.c"), None);
+    }
+}
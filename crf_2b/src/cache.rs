@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::web::Bytes;
+use libfj::robocraft2::SearchPayload;
+
+use crate::config::CacheConfig;
+
+pub const CACHE_HEADER: &str = "X-Cache";
+
+/// Ordered from best to worst, so combining several lookups is `max`
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum CacheStatus {
+    Hit,
+    Stale,
+    Miss,
+}
+
+impl CacheStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hit => "HIT",
+            Self::Stale => "STALE",
+            Self::Miss => "MISS",
+        }
+    }
+}
+
+pub enum Lookup {
+    /// Young enough to serve as-is
+    Fresh(Bytes),
+    /// Past its TTL but still servable; the caller should refresh it
+    Stale(Bytes),
+    /// Stale, but another request is already refreshing it
+    Refreshing(Bytes),
+    Miss,
+}
+
+struct Entry {
+    body: Bytes,
+    stored: Instant,
+    last_used: u64,
+    refreshing: bool,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    entries: HashMap<String, Entry>,
+    bytes: usize,
+    clock: u64,
+}
+
+/// In-memory cache of serialized upstream search responses
+pub struct SearchCache {
+    settings: CacheConfig,
+    inner: Mutex<CacheInner>,
+}
+
+impl SearchCache {
+    pub fn new(settings: CacheConfig) -> Self {
+        Self {
+            settings,
+            inner: Mutex::new(CacheInner::default()),
+        }
+    }
+
+    /// Cache key for a search; equivalent searches map to the same key.
+    ///
+    /// Unset and empty values are dropped and text is trimmed, so `{"text": " "}`
+    /// and `{}` hit the same entry. Object keys are sorted by serde_json.
+    pub fn key(payload: &SearchPayload) -> String {
+        let mut value = serde_json::to_value(payload).unwrap();
+        if let serde_json::Value::Object(map) = &mut value {
+            map.retain(|_, v| match v {
+                serde_json::Value::Null => false,
+                serde_json::Value::String(s) => {
+                    *s = s.trim().to_owned();
+                    !s.is_empty()
+                },
+                _ => true,
+            });
+        }
+        value.to_string()
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.settings.ttl_secs)
+    }
+
+    fn max_age(&self) -> Duration {
+        Duration::from_secs(self.settings.ttl_secs + self.settings.stale_secs)
+    }
+
+    pub fn get(&self, key: &str) -> Lookup {
+        if !self.settings.enabled {
+            return Lookup::Miss;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let clock = inner.clock;
+        let (ttl, max_age) = (self.ttl(), self.max_age());
+        let lookup = match inner.entries.get_mut(key) {
+            Some(entry) => {
+                let age = entry.stored.elapsed();
+                if age <= ttl {
+                    entry.last_used = clock;
+                    Lookup::Fresh(entry.body.clone())
+                } else if age <= max_age {
+                    entry.last_used = clock;
+                    if entry.refreshing {
+                        Lookup::Refreshing(entry.body.clone())
+                    } else {
+                        entry.refreshing = true;
+                        Lookup::Stale(entry.body.clone())
+                    }
+                } else {
+                    Lookup::Miss
+                }
+            },
+            None => Lookup::Miss,
+        };
+        if let Lookup::Miss = lookup {
+            if let Some(entry) = inner.entries.remove(key) {
+                inner.bytes -= entry.body.len();
+            }
+        }
+        lookup
+    }
+
+    pub fn insert(&self, key: String, body: Bytes) {
+        if !self.settings.enabled || body.len() > self.settings.max_bytes {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let entry = Entry {
+            stored: Instant::now(),
+            last_used: inner.clock,
+            refreshing: false,
+            body,
+        };
+        inner.bytes += entry.body.len();
+        if let Some(old) = inner.entries.insert(key, entry) {
+            inner.bytes -= old.body.len();
+        }
+        self.evict(&mut inner);
+    }
+
+    /// A background refresh failed; let the next request try again
+    pub fn refresh_failed(&self, key: &str) {
+        if let Some(entry) = self.inner.lock().unwrap().entries.get_mut(key) {
+            entry.refreshing = false;
+        }
+    }
+
+    fn evict(&self, inner: &mut CacheInner) {
+        let max_age = self.max_age();
+        let mut freed = 0;
+        inner.entries.retain(|_, entry| {
+            let keep = entry.stored.elapsed() <= max_age;
+            if !keep {
+                freed += entry.body.len();
+            }
+            keep
+        });
+        inner.bytes -= freed;
+        while inner.entries.len() > self.settings.max_entries || inner.bytes > self.settings.max_bytes {
+            let oldest = inner.entries.iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => {
+                    let entry = inner.entries.remove(&key).unwrap();
+                    inner.bytes -= entry.body.len();
+                },
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::{to_payload, SearchQuery};
+
+    fn body(text: &str) -> Bytes {
+        Bytes::from(text.to_owned())
+    }
+
+    fn found(lookup: Lookup) -> Option<(&'static str, Bytes)> {
+        match lookup {
+            Lookup::Fresh(body) => Some(("fresh", body)),
+            Lookup::Stale(body) => Some(("stale", body)),
+            Lookup::Refreshing(body) => Some(("refreshing", body)),
+            Lookup::Miss => None,
+        }
+    }
+
+    fn key(json: serde_json::Value) -> String {
+        let query: SearchQuery = serde_json::from_value(json).unwrap();
+        SearchCache::key(&to_payload(&query).unwrap())
+    }
+
+    #[test]
+    fn equivalent_searches_share_a_key() {
+        assert_eq!(key(serde_json::json!({"text": " "})), key(serde_json::json!({})));
+        assert_eq!(key(serde_json::json!({"text": " tank "})), key(serde_json::json!({"text": "tank"})));
+        assert_ne!(key(serde_json::json!({"text": "tank"})), key(serde_json::json!({})));
+    }
+
+    #[test]
+    fn fresh_within_the_ttl() {
+        let cache = SearchCache::new(CacheConfig::default());
+        cache.insert("a".to_owned(), body("A"));
+        assert_eq!(found(cache.get("a")), Some(("fresh", body("A"))));
+        assert_eq!(found(cache.get("b")), None);
+    }
+
+    #[test]
+    fn stale_after_the_ttl_and_refreshed_once() {
+        let cache = SearchCache::new(CacheConfig { ttl_secs: 0, stale_secs: 60, ..CacheConfig::default() });
+        cache.insert("a".to_owned(), body("A"));
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(found(cache.get("a")), Some(("stale", body("A"))));
+        // only the first request refreshes, until that fails
+        assert_eq!(found(cache.get("a")), Some(("refreshing", body("A"))));
+        cache.refresh_failed("a");
+        assert_eq!(found(cache.get("a")), Some(("stale", body("A"))));
+        // a new response starts over
+        cache.insert("a".to_owned(), body("B"));
+        assert_eq!(found(cache.get("a")).unwrap().1, body("B"));
+    }
+
+    #[test]
+    fn gone_after_the_stale_window() {
+        let cache = SearchCache::new(CacheConfig { ttl_secs: 0, stale_secs: 0, ..CacheConfig::default() });
+        cache.insert("a".to_owned(), body("A"));
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(found(cache.get("a")), None);
+        assert_eq!(cache.inner.lock().unwrap().bytes, 0);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_entries() {
+        let cache = SearchCache::new(CacheConfig { max_entries: 2, ..CacheConfig::default() });
+        cache.insert("a".to_owned(), body("A"));
+        cache.insert("b".to_owned(), body("B"));
+        cache.get("a");
+        cache.insert("c".to_owned(), body("C"));
+        assert!(found(cache.get("a")).is_some());
+        assert!(found(cache.get("b")).is_none());
+        assert!(found(cache.get("c")).is_some());
+    }
+
+    #[test]
+    fn evicts_down_to_max_bytes() {
+        let cache = SearchCache::new(CacheConfig { max_bytes: 10, ..CacheConfig::default() });
+        cache.insert("a".to_owned(), body("aaaa"));
+        cache.insert("b".to_owned(), body("bbbb"));
+        cache.insert("c".to_owned(), body("cccc"));
+        assert!(found(cache.get("a")).is_none());
+        assert_eq!(cache.inner.lock().unwrap().bytes, 8);
+        // too big to ever fit
+        cache.insert("d".to_owned(), body("ddddddddddd"));
+        assert!(found(cache.get("d")).is_none());
+        // replacing an entry doesn't count it twice
+        cache.insert("b".to_owned(), body("bb"));
+        assert_eq!(cache.inner.lock().unwrap().bytes, 6);
+    }
+
+    #[test]
+    fn disabled_caches_nothing() {
+        let cache = SearchCache::new(CacheConfig { enabled: false, ..CacheConfig::default() });
+        cache.insert("a".to_owned(), body("A"));
+        assert!(found(cache.get("a")).is_none());
+    }
+}
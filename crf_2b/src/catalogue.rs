@@ -0,0 +1,23 @@
+//! Loading the block and material catalogue.
+//!
+//! The types are in crf_common, shared with the front-end; see there for the format.
+
+use std::path::Path;
+
+use crf_common::{Catalogue, CATALOGUE_VERSION};
+
+use crate::config::ConfigError;
+
+pub fn load(path: &Path) -> Result<Catalogue, ConfigError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+    let catalogue: Catalogue = serde_json::from_str(&text)
+        .map_err(|e| ConfigError::Catalogue(path.to_owned(), e.to_string()))?;
+    if catalogue.version != CATALOGUE_VERSION {
+        return Err(ConfigError::Catalogue(
+            path.to_owned(),
+            format!("version {} is not supported (expected {})", catalogue.version, CATALOGUE_VERSION),
+        ));
+    }
+    Ok(catalogue)
+}
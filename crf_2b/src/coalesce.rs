@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+use tokio::sync::broadcast;
+
+/// Coalesces concurrent calls with the same key into one.
+///
+/// The first caller for a key (the leader) runs the work; everyone who asks for
+/// the same key while it's running waits for and shares the leader's result.
+pub struct SingleFlight<K, V> {
+    in_flight: Mutex<HashMap<K, broadcast::Sender<V>>>,
+}
+
+/// Removes the leader's entry even if the leader is cancelled part way,
+/// which wakes up the waiters so one of them can take over
+struct LeaderGuard<'a, K: Eq + Hash, V> {
+    flight: &'a SingleFlight<K, V>,
+    key: &'a K,
+    finished: bool,
+}
+
+impl<'a, K: Eq + Hash, V> Drop for LeaderGuard<'a, K, V> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.flight.in_flight.lock().unwrap().remove(self.key);
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> SingleFlight<K, V> {
+    pub fn new() -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn run<F, Fut>(&self, key: K, work: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let mut work = Some(work);
+        loop {
+            let waiter = {
+                let mut in_flight = self.in_flight.lock().unwrap();
+                match in_flight.get(&key) {
+                    Some(sender) => Some(sender.subscribe()),
+                    None => {
+                        in_flight.insert(key.clone(), broadcast::channel(1).0);
+                        None
+                    }
+                }
+            };
+            match waiter {
+                Some(mut receiver) => match receiver.recv().await {
+                    Ok(value) => return value,
+                    // the leader went away without an answer, try again
+                    Err(_) => continue,
+                },
+                None => {
+                    let mut guard = LeaderGuard { flight: self, key: &key, finished: false };
+                    let value = (work.take().unwrap())().await;
+                    let sender = self.in_flight.lock().unwrap().remove(&key);
+                    guard.finished = true;
+                    if let Some(sender) = sender {
+                        // no receivers is fine, nobody else asked
+                        let _ = sender.send(value.clone());
+                    }
+                    return value;
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use actix_web::rt;
+    use tokio::sync::Notify;
+
+    use super::*;
+
+    type Flight = Arc<SingleFlight<&'static str, usize>>;
+
+    /// Run `key` on a task, with work that counts its runs and answers `value` once released
+    fn start(flight: &Flight, key: &'static str, value: usize, runs: &Arc<AtomicUsize>, release: &Arc<Notify>) -> rt::task::JoinHandle<usize> {
+        let (flight, runs, release) = (flight.clone(), runs.clone(), release.clone());
+        rt::spawn(async move {
+            flight.run(key, || async move {
+                runs.fetch_add(1, Ordering::SeqCst);
+                release.notified().await;
+                value
+            }).await
+        })
+    }
+
+    /// Let spawned tasks get as far as they can
+    async fn settle() {
+        rt::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    #[actix_web::test]
+    async fn concurrent_calls_share_the_leaders_result() {
+        let (flight, runs, release) = (Flight::default(), Arc::new(AtomicUsize::new(0)), Arc::new(Notify::new()));
+        let leader = start(&flight, "k", 1, &runs, &release);
+        settle().await;
+        let waiters: Vec<_> = (2..5).map(|value| start(&flight, "k", value, &runs, &release)).collect();
+        settle().await;
+        release.notify_waiters();
+        assert_eq!(leader.await.unwrap(), 1);
+        for waiter in waiters {
+            assert_eq!(waiter.await.unwrap(), 1);
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert!(flight.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn different_keys_and_later_calls_run_again() {
+        let (flight, runs, release) = (Flight::default(), Arc::new(AtomicUsize::new(0)), Arc::new(Notify::new()));
+        let a = start(&flight, "a", 1, &runs, &release);
+        let b = start(&flight, "b", 2, &runs, &release);
+        settle().await;
+        release.notify_waiters();
+        assert_eq!((a.await.unwrap(), b.await.unwrap()), (1, 2));
+        let again = start(&flight, "a", 3, &runs, &release);
+        settle().await;
+        release.notify_waiters();
+        assert_eq!(again.await.unwrap(), 3);
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+    }
+
+    #[actix_web::test]
+    async fn a_waiter_takes_over_from_a_cancelled_leader() {
+        let (flight, runs, release) = (Flight::default(), Arc::new(AtomicUsize::new(0)), Arc::new(Notify::new()));
+        let leader = start(&flight, "k", 1, &runs, &release);
+        settle().await;
+        let waiter = start(&flight, "k", 2, &runs, &release);
+        settle().await;
+        leader.abort();
+        settle().await;
+        release.notify_waiters();
+        assert_eq!(waiter.await.unwrap(), 2);
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        assert!(flight.in_flight.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,527 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// Command line flags. Every setting can also come from an environment variable,
+/// and anything given here overrides the config file.
+#[derive(Parser, Debug)]
+#[command(version, about = "Back-end for the unofficial CRF2 site")]
+pub struct Cli {
+    /// TOML config file to load
+    #[arg(short, long, env = "CRF_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Address(es) to listen on, e.g. 127.0.0.1:45554 (repeat or separate with commas)
+    #[arg(long, env = "CRF_BIND", value_delimiter = ',')]
+    pub bind: Vec<String>,
+    /// Directory containing the built front-end (trunk's dist folder)
+    #[arg(long, env = "CRF_STATIC_ROOT")]
+    pub static_root: Option<PathBuf>,
+    /// RC2 portal username
+    #[arg(long, env = "CRF_USERNAME")]
+    pub username: Option<String>,
+    /// File containing the RC2 portal password
+    #[arg(long, env = "CRF_PASSWORD_FILE")]
+    pub password_file: Option<PathBuf>,
+    /// Number of HTTP worker threads (defaults to one per CPU core)
+    #[arg(long, env = "CRF_WORKERS")]
+    pub workers: Option<usize>,
+    /// JSON file naming block and material IDs
+    #[arg(long, env = "CRF_CATALOGUE")]
+    pub catalogue: Option<PathBuf>,
+    /// Print the resolved configuration and exit
+    #[arg(long)]
+    pub print_config: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind: Vec<String>,
+    pub static_root: PathBuf,
+    pub workers: Option<usize>,
+    pub catalogue: PathBuf,
+    pub credentials: Credentials,
+    pub auth: AuthConfig,
+    pub cache: CacheConfig,
+    pub index: IndexConfig,
+    pub filter: FilterConfig,
+    pub store: StoreConfig,
+    pub crawler: CrawlerConfig,
+    pub offline: OfflineConfig,
+    pub fulltext: FullTextConfig,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Credentials {
+    pub username: Option<String>,
+    pub password_file: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Seconds before the portal token expires to log in again
+    pub refresh_margin_secs: u64,
+    /// Seconds between logins when the token doesn't say when it expires
+    pub refresh_interval_secs: u64,
+    /// First delay before retrying a failed login, doubled after every failure
+    pub retry_initial_secs: u64,
+    /// Longest delay between login retries
+    pub retry_max_secs: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    /// Seconds a search response is served without asking upstream
+    pub ttl_secs: u64,
+    /// Seconds after the TTL during which the old response is still served
+    /// while it's refreshed in the background
+    pub stale_secs: u64,
+    pub max_entries: usize,
+    /// Upper bound on the total size of cached response bodies
+    pub max_bytes: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_secs: 60,
+            stale_secs: 5 * 60,
+            max_entries: 1000,
+            max_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct IndexConfig {
+    /// Most robots to remember from search results for lookups by ID
+    pub max_robots: usize,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            max_robots: 50_000,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    /// Results asked of upstream per page while post-filtering
+    pub page_size: usize,
+    /// Most upstream pages scanned for one post-filtered search, per creator when it has several
+    pub max_pages: usize,
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        Self {
+            page_size: 100,
+            max_pages: 10,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreConfig {
+    pub enabled: bool,
+    /// SQLite database file, created if missing
+    pub path: PathBuf,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: PathBuf::from("crf_2b.sqlite"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrawlerConfig {
+    pub enabled: bool,
+    /// Seconds between crawl runs; also the window `request_budget` applies to
+    pub interval_secs: u64,
+    /// Most upstream requests per interval
+    pub request_budget: usize,
+    /// Seconds to wait between upstream requests
+    pub request_interval_secs: u64,
+    /// Results asked of upstream per page
+    pub page_size: usize,
+}
+
+impl Default for CrawlerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: 24 * 60 * 60,
+            request_budget: 2000,
+            request_interval_secs: 5,
+            page_size: 100,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum OfflineMode {
+    /// Only ever search upstream
+    Never,
+    /// Search the store when upstream fails
+    Fallback,
+    /// Only ever search the store
+    Always,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct OfflineConfig {
+    pub mode: OfflineMode,
+}
+
+impl Default for OfflineConfig {
+    fn default() -> Self {
+        Self {
+            mode: OfflineMode::Fallback,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct FullTextConfig {
+    pub enabled: bool,
+    /// Seconds between picking up robots newly added to the store
+    pub sync_secs: u64,
+}
+
+impl Default for FullTextConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            sync_secs: 30,
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            refresh_margin_secs: 5 * 60,
+            refresh_interval_secs: 45 * 60,
+            retry_initial_secs: 5,
+            retry_max_secs: 5 * 60,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: vec!["127.0.0.1:45554".to_owned()],
+            static_root: PathBuf::from("../dist"),
+            workers: None,
+            catalogue: PathBuf::from("../catalogue.json"),
+            credentials: Credentials::default(),
+            auth: AuthConfig::default(),
+            cache: CacheConfig::default(),
+            index: IndexConfig::default(),
+            filter: FilterConfig::default(),
+            store: StoreConfig::default(),
+            crawler: CrawlerConfig::default(),
+            offline: OfflineConfig::default(),
+            fulltext: FullTextConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Catalogue(PathBuf, String),
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "Cannot read {}: {}", path.display(), e),
+            Self::Parse(path, e) => write!(f, "Invalid config file {}: {}", path.display(), e),
+            Self::Catalogue(path, e) => write!(f, "Invalid catalogue {}: {}", path.display(), e),
+            Self::Invalid(problems) => {
+                write!(f, "Invalid configuration:")?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Build the configuration from defaults, then the config file, then env/CLI.
+    /// The result still needs to be checked with [`Config::validate`].
+    pub fn load(cli: &Cli) -> Result<Self, ConfigError> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply_cli(cli);
+        Ok(config)
+    }
+
+    /// Load a config file. A relative `catalogue` in it is relative to the file,
+    /// since it's usually kept alongside; everything else is relative to the working directory.
+    fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+        let value: toml::Value = toml::from_str(&text)
+            .map_err(|e| ConfigError::Parse(path.to_owned(), e))?;
+        let sets_catalogue = value.get("catalogue").is_some();
+        let mut config: Self = value.try_into()
+            .map_err(|e| ConfigError::Parse(path.to_owned(), e))?;
+        if sets_catalogue && config.catalogue.is_relative() {
+            if let Some(dir) = path.parent() {
+                config.catalogue = dir.join(&config.catalogue);
+            }
+        }
+        Ok(config)
+    }
+
+    fn apply_cli(&mut self, cli: &Cli) {
+        if !cli.bind.is_empty() {
+            self.bind = cli.bind.clone();
+        }
+        if let Some(static_root) = &cli.static_root {
+            self.static_root = static_root.clone();
+        }
+        if let Some(username) = &cli.username {
+            self.credentials.username = Some(username.clone());
+        }
+        if let Some(password_file) = &cli.password_file {
+            self.credentials.password_file = Some(password_file.clone());
+        }
+        if cli.workers.is_some() {
+            self.workers = cli.workers;
+        }
+        if let Some(catalogue) = &cli.catalogue {
+            self.catalogue = catalogue.clone();
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        if self.bind.is_empty() {
+            problems.push("at least one bind address is required".to_owned());
+        }
+        for addr in self.bind.iter() {
+            if addr.parse::<SocketAddr>().is_err() {
+                problems.push(format!("bind address `{}` is not a valid ip:port", addr));
+            }
+        }
+        if !self.static_root.is_dir() {
+            problems.push(format!("static root {} is not a directory", self.static_root.display()));
+        }
+        if self.workers == Some(0) {
+            problems.push("workers must be at least 1".to_owned());
+        }
+        if self.auth.refresh_interval_secs == 0 {
+            problems.push("auth.refresh_interval_secs must be at least 1".to_owned());
+        }
+        if self.auth.retry_initial_secs == 0 || self.auth.retry_initial_secs > self.auth.retry_max_secs {
+            problems.push("auth.retry_initial_secs must be between 1 and auth.retry_max_secs".to_owned());
+        }
+        if self.cache.enabled && (self.cache.max_entries == 0 || self.cache.max_bytes == 0) {
+            problems.push("cache.max_entries and cache.max_bytes must be at least 1 when the cache is enabled".to_owned());
+        }
+        if self.index.max_robots == 0 {
+            problems.push("index.max_robots must be at least 1".to_owned());
+        }
+        if self.filter.page_size == 0 || self.filter.max_pages == 0 {
+            problems.push("filter.page_size and filter.max_pages must be at least 1".to_owned());
+        }
+        if self.store.enabled && self.store.path.as_os_str().is_empty() {
+            problems.push("store.path is required when the store is enabled".to_owned());
+        }
+        if self.crawler.enabled {
+            if !self.store.enabled {
+                problems.push("the crawler needs the store to be enabled".to_owned());
+            }
+            if self.crawler.interval_secs == 0 || self.crawler.request_budget == 0 || self.crawler.page_size == 0 {
+                problems.push("crawler.interval_secs, crawler.request_budget and crawler.page_size must be at least 1".to_owned());
+            }
+        }
+        if self.offline.mode == OfflineMode::Always && !self.store.enabled {
+            problems.push("offline.mode = \"always\" needs the store to be enabled".to_owned());
+        }
+        if self.fulltext.enabled {
+            if !self.store.enabled {
+                problems.push("full-text search needs the store to be enabled".to_owned());
+            }
+            if self.fulltext.sync_secs == 0 {
+                problems.push("fulltext.sync_secs must be at least 1".to_owned());
+            }
+        }
+        match (&self.credentials.username, &self.credentials.password_file) {
+            (Some(_), Some(password_file)) => {
+                if !password_file.is_file() {
+                    problems.push(format!("password file {} does not exist", password_file.display()));
+                }
+            },
+            (None, None) => problems.push("portal credentials (username and password_file) are required".to_owned()),
+            (Some(_), None) => problems.push("password_file is required when username is set".to_owned()),
+            (None, Some(_)) => problems.push("username is required when password_file is set".to_owned()),
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    /// The resolved config as TOML. Secrets are never part of the config itself,
+    /// only the path to the file holding them.
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).unwrap()
+    }
+}
+
+impl Credentials {
+    pub fn username(&self) -> &str {
+        self.username.as_deref().unwrap_or_default()
+    }
+
+    /// Read the password from the secret file, ignoring surrounding whitespace
+    pub fn password(&self) -> Result<String, ConfigError> {
+        let path = self.password_file.clone().unwrap_or_default();
+        std::fs::read_to_string(&path)
+            .map(|s| s.trim().to_owned())
+            .map_err(|e| ConfigError::Io(path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write a config file to a fresh temporary directory
+    fn config_file(name: &str, text: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("crf_2b-config-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("crf_2b.toml");
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn catalogue_is_relative_to_the_config_file() {
+        let path = config_file("relative", "catalogue = \"../catalogue.json\"\n");
+        let config = Config::from_file(&path).unwrap();
+        assert_eq!(config.catalogue, path.parent().unwrap().join("../catalogue.json"));
+    }
+
+    #[test]
+    fn absolute_and_default_catalogues_are_kept() {
+        let path = config_file("absolute", "catalogue = \"/srv/catalogue.json\"\n");
+        assert_eq!(Config::from_file(&path).unwrap().catalogue, PathBuf::from("/srv/catalogue.json"));
+        let path = config_file("default", "workers = 2\n");
+        assert_eq!(Config::from_file(&path).unwrap().catalogue, Config::default().catalogue);
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        let path = config_file("unknown", "catalog = \"typo.json\"\n");
+        assert!(matches!(Config::from_file(&path), Err(ConfigError::Parse(..))));
+    }
+
+    /// A config that passes validation, with its static root and password file in a temporary directory
+    fn valid(name: &str) -> Config {
+        let password_file = config_file(&format!("valid-{}", name), "");
+        Config {
+            static_root: password_file.parent().unwrap().to_owned(),
+            credentials: Credentials { username: Some("crawler".to_owned()), password_file: Some(password_file) },
+            ..Config::default()
+        }
+    }
+
+    fn problems(config: &Config) -> Vec<String> {
+        match config.validate() {
+            Ok(()) => Vec::new(),
+            Err(ConfigError::Invalid(problems)) => problems,
+            Err(e) => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn defaults_with_credentials_are_valid() {
+        assert_eq!(problems(&valid("defaults")), Vec::<String>::new());
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let mut config = valid("every");
+        config.bind = vec!["localhost".to_owned()];
+        config.workers = Some(0);
+        config.filter.max_pages = 0;
+        assert_eq!(problems(&config), [
+            "bind address `localhost` is not a valid ip:port",
+            "workers must be at least 1",
+            "filter.page_size and filter.max_pages must be at least 1",
+        ]);
+    }
+
+    #[test]
+    fn features_needing_the_store() {
+        let mut config = valid("store");
+        config.store.enabled = false;
+        config.crawler.enabled = true;
+        config.offline.mode = OfflineMode::Always;
+        config.fulltext.enabled = true;
+        assert_eq!(problems(&config), [
+            "the crawler needs the store to be enabled",
+            "offline.mode = \"always\" needs the store to be enabled",
+            "full-text search needs the store to be enabled",
+        ]);
+        config.crawler.enabled = false;
+        config.offline.mode = OfflineMode::Never;
+        config.fulltext.enabled = false;
+        assert!(problems(&config).is_empty());
+    }
+
+    #[test]
+    fn credentials_come_in_pairs() {
+        let mut config = valid("credentials");
+        config.credentials.username = None;
+        assert_eq!(problems(&config), ["username is required when password_file is set"]);
+        config.credentials.password_file = None;
+        assert_eq!(problems(&config), ["portal credentials (username and password_file) are required"]);
+        config.credentials.username = Some("crawler".to_owned());
+        config.credentials.password_file = Some(PathBuf::from("/nonexistent/password"));
+        assert_eq!(problems(&config), ["password file /nonexistent/password does not exist"]);
+    }
+
+    #[test]
+    fn auth_retry_has_to_fit_under_its_maximum() {
+        let mut config = valid("retry");
+        config.auth.retry_initial_secs = config.auth.retry_max_secs + 1;
+        assert_eq!(problems(&config), ["auth.retry_initial_secs must be between 1 and auth.retry_max_secs"]);
+    }
+}
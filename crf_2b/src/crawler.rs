@@ -0,0 +1,192 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use actix_web::{rt, web};
+
+use crate::auth::{TokenManager, UpstreamError};
+use crate::config::CrawlerConfig;
+use crate::error::{ApiError, ErrorCode};
+use crate::model::SearchResults;
+use crate::query::{to_payload, SearchQuery, SORT_OPTIONS};
+use crate::store::{self, CrawlRun, Store};
+
+/// How long to wait before trying again after a failure
+const RETRY_SECS: u64 = 60;
+
+/// Why a crawl stopped before finishing
+enum Paused {
+    /// Used up this interval's requests
+    Budget,
+    /// Upstream or the store failed; try again next interval
+    Error(String),
+}
+
+/// Pages through every sort of the CRF search into the store, so it holds
+/// everything and not just what visitors searched for.
+///
+/// Runs start every `interval_secs` and make at most `request_budget` upstream
+/// requests per interval, `request_interval_secs` apart. Progress is saved after
+/// every page, so a crawl that runs out of budget, fails or is interrupted by a
+/// restart carries on where it stopped, and still marks what it didn't see as
+/// disappeared when it finishes. A crawl upstream refused to page all the way
+/// through is truncated: robots it missed aren't marked disappeared.
+pub struct Crawler {
+    tokens: Arc<TokenManager>,
+    store: Arc<Store>,
+    settings: CrawlerConfig,
+}
+
+impl Crawler {
+    pub fn new(tokens: Arc<TokenManager>, store: Arc<Store>, settings: CrawlerConfig) -> Self {
+        Self {
+            tokens,
+            store,
+            settings,
+        }
+    }
+
+    pub fn spawn(self) {
+        rt::spawn(async move {
+            let interval = Duration::from_secs(self.settings.interval_secs);
+            let mut window_start = Instant::now();
+            let mut budget = self.settings.request_budget;
+            loop {
+                if window_start.elapsed() >= interval {
+                    window_start = Instant::now();
+                    budget = self.settings.request_budget;
+                }
+                let failed = match self.next_run().await {
+                    Ok(Some(run)) => !self.crawl(run, &mut budget).await,
+                    Ok(None) => false,
+                    Err(e) => {
+                        println!("Crawler cannot read crawl runs: {}", e);
+                        true
+                    },
+                };
+                let window_left = interval.saturating_sub(window_start.elapsed());
+                let wait = if failed {
+                    window_left.min(Duration::from_secs(RETRY_SECS))
+                } else {
+                    window_left
+                };
+                rt::time::sleep(wait.max(Duration::from_secs(1))).await;
+            }
+        });
+    }
+
+    /// The unfinished crawl to resume, or a new one if the last started long enough ago
+    async fn next_run(&self) -> Result<Option<CrawlRun>, String> {
+        let store = self.store.clone();
+        let interval = self.settings.interval_secs as i64;
+        web::block(move || {
+            if let Some(run) = store.resume_crawl()? {
+                println!("Crawler resuming run {} at sort {} page {}", run.id, run.sort_index, run.page);
+                return Ok(Some(run));
+            }
+            let now = store::now();
+            match store.recent_crawls(1)?.first() {
+                Some(last) if last.started + interval > now => Ok(None),
+                _ => store.start_crawl(now).map(Some),
+            }
+        }).await
+            .map_err(|e| e.to_string())?
+            .map_err(|e: rusqlite::Error| e.to_string())
+    }
+
+    /// Crawl until finished or out of budget; false if it stopped on an error
+    async fn crawl(&self, mut run: CrawlRun, budget: &mut usize) -> bool {
+        match self.crawl_pages(&mut run, budget).await {
+            Ok(()) => {
+                let store = self.store.clone();
+                let mut finished = run.clone();
+                match web::block(move || store.finish_crawl(&mut finished, store::now()).map(|_| finished)).await {
+                    Ok(Ok(run)) if run.truncated => println!(
+                        "Crawl run {} finished: {} requests, {} robots seen, {} added, {} changed; truncated, so none marked disappeared",
+                        run.id, run.requests, run.robots_seen, run.added, run.changed,
+                    ),
+                    Ok(Ok(run)) => println!(
+                        "Crawl run {} finished: {} requests, {} robots seen, {} added, {} changed, {} disappeared",
+                        run.id, run.requests, run.robots_seen, run.added, run.changed, run.disappeared,
+                    ),
+                    Ok(Err(e)) => {
+                        println!("Crawler cannot finish run {}: {}", run.id, e);
+                        return false;
+                    },
+                    Err(e) => {
+                        println!("Crawler cannot finish run {}: {}", run.id, e);
+                        return false;
+                    },
+                }
+                true
+            },
+            Err(Paused::Budget) => {
+                println!("Crawl run {} used its request budget, continuing next interval", run.id);
+                true
+            },
+            Err(Paused::Error(e)) => {
+                println!("Crawl run {} paused: {}", run.id, e);
+                false
+            },
+        }
+    }
+
+    async fn crawl_pages(&self, run: &mut CrawlRun, budget: &mut usize) -> Result<(), Paused> {
+        let page_size = self.settings.page_size;
+        while run.sort_index < SORT_OPTIONS.len() {
+            if *budget == 0 {
+                return Err(Paused::Budget);
+            }
+            let query: SearchQuery = serde_json::from_value(serde_json::json!({
+                "sortBy": SORT_OPTIONS[run.sort_index],
+                "orderBy": "ascending",
+                "page": run.page,
+                "count": page_size,
+            })).unwrap();
+            let payload = to_payload(&query).map_err(|e| Paused::Error(e.to_string()))?;
+            *budget -= 1;
+            run.requests += 1;
+            match self.tokens.search(payload).await {
+                Ok(response) => {
+                    let results: SearchResults = serde_json::to_value(&response)
+                        .and_then(serde_json::from_value)
+                        .map_err(|e| Paused::Error(format!("Cannot read CRF results: {}", e)))?;
+                    let found = results.results.len();
+                    let store = self.store.clone();
+                    let crawl = run.id;
+                    let counts = web::block(move || store.upsert(&results.results, store::now(), Some(crawl))).await
+                        .map_err(|e| Paused::Error(e.to_string()))?
+                        .map_err(|e| Paused::Error(e.to_string()))?;
+                    run.added += counts.added;
+                    run.changed += counts.changed;
+                    if found < page_size {
+                        run.sort_index += 1;
+                        run.page = 1;
+                    } else {
+                        run.page += 1;
+                    }
+                },
+                Err(UpstreamError::Unavailable) => {
+                    return Err(Paused::Error("not logged in to the portal".to_owned()));
+                },
+                Err(e) => {
+                    let error = ApiError::from_upstream(&e);
+                    if error.code != ErrorCode::UpstreamRejected {
+                        return Err(Paused::Error(error.to_string()));
+                    }
+                    // upstream won't page this far; move on, but this run can't have seen everything
+                    println!("Crawl run {} stopped sort {} at page {}: {}", run.id, SORT_OPTIONS[run.sort_index], run.page, error);
+                    run.truncated = true;
+                    run.sort_index += 1;
+                    run.page = 1;
+                },
+            }
+            let store = self.store.clone();
+            let progress = run.clone();
+            web::block(move || store.save_crawl(&progress)).await
+                .map_err(|e| Paused::Error(e.to_string()))?
+                .map_err(|e| Paused::Error(e.to_string()))?;
+            rt::time::sleep(Duration::from_secs(self.settings.request_interval_secs)).await;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+//! Creator name suggestions, so people can search by creator without knowing GUIDs.
+
+use serde::Serialize;
+
+/// Most suggestions asked for in one request
+pub const MAX_SUGGESTIONS: usize = 50;
+/// Suggestions returned when the request doesn't say
+pub const DEFAULT_SUGGESTIONS: usize = 10;
+
+/// A creator crf_2b has seen robots from
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatorSuggestion {
+    pub id: String,
+    /// Name on their most recently seen robot
+    pub name: String,
+    /// Robots by them that crf_2b has seen
+    pub robots: usize,
+}
+
+/// When crf_2b first saw a creator's robots. The CRF doesn't say when robots were
+/// uploaded, so this is the closest there is, and only as old as the store.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatorSeen {
+    /// Robots by them in the store
+    pub robots: usize,
+    /// When the first of them was first seen (unix seconds)
+    pub first_seen: i64,
+    /// When the newest of them was first seen (unix seconds)
+    pub newest_seen: i64,
+}
+
+/// Best suggestions for what someone typed, from creators whose name or ID starts with it.
+///
+/// Exact names come first, then names starting with the prefix as typed, then the rest;
+/// within each, creators with more robots first.
+pub fn rank(prefix: &str, mut candidates: Vec<CreatorSuggestion>, limit: usize) -> Vec<CreatorSuggestion> {
+    let lower = prefix.to_lowercase();
+    candidates.retain(|c| c.name.to_lowercase().starts_with(&lower) || c.id.to_lowercase().starts_with(&lower));
+    candidates.sort_by_cached_key(|c| {
+        let tier = if c.name.to_lowercase() == lower {
+            0
+        } else if c.name.starts_with(prefix) {
+            1
+        } else {
+            2
+        };
+        (tier, std::cmp::Reverse(c.robots), c.name.len(), c.name.clone())
+    });
+    candidates.truncate(limit);
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn creator(id: &str, name: &str, robots: usize) -> CreatorSuggestion {
+        CreatorSuggestion { id: id.to_owned(), name: name.to_owned(), robots }
+    }
+
+    fn names(ranked: &[CreatorSuggestion]) -> Vec<&str> {
+        ranked.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    #[test]
+    fn exact_then_case_sensitive_prefix_then_most_robots() {
+        let candidates = vec![
+            creator("1", "bobby", 50),
+            creator("2", "Bobcat", 3),
+            creator("3", "BOB", 1),
+            creator("4", "Bobby", 2),
+        ];
+        assert_eq!(names(&rank("Bob", candidates, 10)), ["BOB", "Bobcat", "Bobby", "bobby"]);
+    }
+
+    #[test]
+    fn ties_prefer_shorter_then_alphabetical_names() {
+        let candidates = vec![creator("1", "abcd", 1), creator("2", "abc", 1), creator("3", "abce", 1)];
+        assert_eq!(names(&rank("ab", candidates, 10)), ["abc", "abcd", "abce"]);
+    }
+
+    #[test]
+    fn matches_ids_and_drops_the_rest() {
+        let candidates = vec![creator("ab12", "zed", 1), creator("cd34", "yan", 5)];
+        assert_eq!(names(&rank("AB", candidates, 10)), ["zed"]);
+    }
+
+    #[test]
+    fn limit() {
+        let candidates = (0..20).map(|i| creator(&i.to_string(), &format!("name{}", i), i)).collect();
+        let ranked = rank("name", candidates, 3);
+        assert_eq!(names(&ranked), ["name19", "name18", "name17"]);
+        assert!(rank("", vec![creator("1", "a", 1)], 0).is_empty());
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt;
+use std::future::{ready, Ready};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::{dev::Payload, http::StatusCode, http::header::ContentType, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use libfj::robocraft2::FactoryError;
+use serde::Serialize;
+
+use crate::auth::UpstreamError;
+use crate::query::FieldError;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Identifies a request in logs and error responses.
+///
+/// Taken from the `X-Request-Id` header when a proxy in front of us already set one.
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+static REQUEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+impl RequestId {
+    fn generate() -> Self {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let count = REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed);
+        Self(format!("{:x}-{:06x}", started, count))
+    }
+
+    /// The ID for a request, for use outside of extractors
+    pub fn of(req: &HttpRequest) -> Self {
+        Self::from_header(req).unwrap_or_else(Self::generate)
+    }
+
+    fn from_header(req: &HttpRequest) -> Option<Self> {
+        let value = req.headers().get(REQUEST_ID_HEADER)?.to_str().ok()?;
+        if !value.is_empty() && value.len() <= 64 && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            Some(Self(value.to_owned()))
+        } else {
+            None
+        }
+    }
+}
+
+impl FromRequest for RequestId {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(Self::of(req)))
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request couldn't be parsed
+    InvalidQuery,
+    /// The thing asked for doesn't exist (or we've never seen it)
+    NotFound,
+    /// Not logged in to the portal
+    Unavailable,
+    /// Couldn't talk to upstream at all
+    UpstreamUnreachable,
+    /// Upstream refused the search (bad parameters)
+    UpstreamRejected,
+    /// Upstream answered with an unexpected status
+    UpstreamError,
+    /// The local robot store failed
+    StoreError,
+}
+
+impl ErrorCode {
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::InvalidQuery => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            Self::UpstreamUnreachable => StatusCode::BAD_GATEWAY,
+            Self::UpstreamRejected => StatusCode::BAD_REQUEST,
+            Self::UpstreamError => StatusCode::BAD_GATEWAY,
+            Self::StoreError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// The error body of every /crf-api endpoint
+#[derive(Serialize, Debug)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub upstream_status: Option<u16>,
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+    #[serde(skip)]
+    status: StatusCode,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            upstream_status: None,
+            request_id: None,
+            fields: Vec::new(),
+            status: code.status(),
+        }
+    }
+
+    pub fn with_request_id(mut self, id: &RequestId) -> Self {
+        self.request_id = Some(id.0.clone());
+        self
+    }
+
+    /// The search failed validation
+    pub fn invalid_fields(fields: Vec<FieldError>) -> Self {
+        let message = fields.iter()
+            .map(|f| format!("{} {}", f.field, f.message))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut error = Self::new(ErrorCode::InvalidQuery, message);
+        error.fields = fields;
+        error
+    }
+
+    /// Error handler for the JSON and query string extractors
+    pub fn invalid_query(e: impl fmt::Display, req: &HttpRequest) -> actix_web::Error {
+        Self::new(ErrorCode::InvalidQuery, e.to_string())
+            .with_request_id(&RequestId::of(req))
+            .into()
+    }
+
+    pub fn from_upstream(e: &UpstreamError) -> Self {
+        match e {
+            UpstreamError::Unavailable => Self::new(ErrorCode::Unavailable, "Not connected to the CRF, try again later"),
+            UpstreamError::Factory(FactoryError::Protocol(e)) => {
+                Self::new(ErrorCode::UpstreamUnreachable, format!("CRF request failed: {}", e))
+            },
+            UpstreamError::Factory(FactoryError::Response(e)) => {
+                Self::new(ErrorCode::UpstreamRejected, e.error_message.clone())
+            },
+            UpstreamError::Factory(FactoryError::ResponseCode(e, status)) => {
+                let mut error = Self::new(ErrorCode::UpstreamError, e.to_string());
+                error.upstream_status = Some(*status);
+                // pass client errors through; auth problems and server errors are ours to deal with
+                if let Ok(code) = StatusCode::from_u16(*status) {
+                    if code.is_client_error() && code != StatusCode::UNAUTHORIZED && code != StatusCode::FORBIDDEN {
+                        error.code = ErrorCode::UpstreamRejected;
+                        error.status = code;
+                    }
+                }
+                error
+            },
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)?;
+        if let Some(status) = self.upstream_status {
+            write!(f, " (upstream status:{})", status)?;
+        }
+        if let Some(id) = &self.request_id {
+            write!(f, " [{}]", id)?;
+        }
+        Ok(())
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status);
+        response.content_type(ContentType::json());
+        if let Some(id) = &self.request_id {
+            response.insert_header((REQUEST_ID_HEADER, id.as_str()));
+        }
+        if self.code == ErrorCode::Unavailable {
+            response.insert_header(("Retry-After", "30"));
+        }
+        response.body(serde_json::to_string(self).unwrap())
+    }
+}
@@ -0,0 +1,150 @@
+//! Filters the CRF can't do itself, applied by crf_2b to upstream results.
+
+use crate::model::ResultItem;
+
+/// Robots with a price in range.
+///
+/// The CRF's search has no price parameters to pass this on to, so it's always applied here.
+/// Robots without any prices aren't for sale, so they never match.
+#[derive(Clone, Debug)]
+pub struct PriceFilter {
+    /// Currency code; any currency when unset
+    pub currency: Option<usize>,
+    pub minimum: usize,
+    pub maximum: Option<usize>,
+}
+
+impl PriceFilter {
+    fn in_range(&self, amount: usize) -> bool {
+        amount >= self.minimum && self.maximum.map(|max| amount <= max).unwrap_or(true)
+    }
+
+    pub fn matches(&self, item: &ResultItem) -> bool {
+        item.prices.iter().any(|price| {
+            self.currency.map(|c| c == price.currency).unwrap_or(true) && self.in_range(price.amount)
+        })
+    }
+}
+
+/// Robots with at least (or, when excluding, fewer than) `count` of a block
+#[derive(Clone, Debug)]
+pub struct BlockFilter {
+    pub id: usize,
+    pub count: usize,
+    pub include: bool,
+}
+
+impl BlockFilter {
+    pub fn matches(&self, item: &ResultItem) -> bool {
+        let count = item.robot.blockCounts.get(&self.id).copied().unwrap_or(0);
+        (count >= self.count) == self.include
+    }
+}
+
+/// Robots that use (or don't use) a material
+#[derive(Clone, Debug)]
+pub struct MaterialFilter {
+    pub id: usize,
+    pub include: bool,
+}
+
+impl MaterialFilter {
+    pub fn matches(&self, item: &ResultItem) -> bool {
+        item.robot.materialsUsed.contains(&self.id) == self.include
+    }
+}
+
+/// Everything a result has to pass after it comes back from upstream
+#[derive(Clone, Debug, Default)]
+pub struct PostFilter {
+    pub price: Option<PriceFilter>,
+    pub blocks: Vec<BlockFilter>,
+    pub materials: Vec<MaterialFilter>,
+}
+
+impl PostFilter {
+    pub fn is_empty(&self) -> bool {
+        self.price.is_none() && self.blocks.is_empty() && self.materials.is_empty()
+    }
+
+    pub fn matches(&self, item: &ResultItem) -> bool {
+        self.price.as_ref().map(|f| f.matches(item)).unwrap_or(true)
+            && self.blocks.iter().all(|f| f.matches(item))
+            && self.materials.iter().all(|f| f.matches(item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{test_robot, Price};
+
+    fn item(prices: &[(usize, usize)], blocks: &[(usize, usize)], materials: &[usize]) -> ResultItem {
+        let mut item = test_robot("r", "c", "Creator");
+        item.robot.blockCounts = blocks.iter().copied().collect();
+        item.robot.materialsUsed = materials.to_vec();
+        item.prices = prices.iter().map(|&(currency, amount)| Price { currency, amount }).collect();
+        item
+    }
+
+    fn price(currency: Option<usize>, minimum: usize, maximum: Option<usize>) -> PriceFilter {
+        PriceFilter { currency, minimum, maximum }
+    }
+
+    #[test]
+    fn price_range_is_inclusive() {
+        let robot = item(&[(0, 100)], &[], &[]);
+        assert!(price(None, 100, Some(100)).matches(&robot));
+        assert!(price(None, 0, None).matches(&robot));
+        assert!(!price(None, 101, None).matches(&robot));
+        assert!(!price(None, 0, Some(99)).matches(&robot));
+    }
+
+    #[test]
+    fn price_currency_has_to_match_the_same_price() {
+        let robot = item(&[(0, 100), (1, 5)], &[], &[]);
+        assert!(price(Some(1), 0, Some(10)).matches(&robot));
+        assert!(!price(Some(0), 0, Some(10)).matches(&robot));
+        assert!(!price(Some(2), 0, None).matches(&robot));
+    }
+
+    #[test]
+    fn robots_not_for_sale_never_match_a_price() {
+        let robot = item(&[], &[], &[]);
+        assert!(!price(None, 0, None).matches(&robot));
+        assert!(!price(None, 0, Some(0)).matches(&robot));
+        assert!(price(None, 0, Some(0)).matches(&item(&[(0, 0)], &[], &[])));
+    }
+
+    #[test]
+    fn blocks_include_at_least_and_exclude_fewer_than() {
+        let robot = item(&[], &[(7, 3)], &[]);
+        assert!(BlockFilter { id: 7, count: 3, include: true }.matches(&robot));
+        assert!(!BlockFilter { id: 7, count: 4, include: true }.matches(&robot));
+        assert!(BlockFilter { id: 7, count: 4, include: false }.matches(&robot));
+        assert!(!BlockFilter { id: 7, count: 1, include: false }.matches(&robot));
+        assert!(BlockFilter { id: 8, count: 1, include: false }.matches(&robot));
+    }
+
+    #[test]
+    fn materials() {
+        let robot = item(&[], &[], &[2]);
+        assert!(MaterialFilter { id: 2, include: true }.matches(&robot));
+        assert!(!MaterialFilter { id: 2, include: false }.matches(&robot));
+        assert!(MaterialFilter { id: 3, include: false }.matches(&robot));
+    }
+
+    #[test]
+    fn post_filter_needs_every_part() {
+        let robot = item(&[(0, 10)], &[(7, 1)], &[2]);
+        let mut filter = PostFilter::default();
+        assert!(filter.is_empty());
+        assert!(filter.matches(&robot));
+        filter.price = Some(price(None, 0, Some(10)));
+        filter.blocks.push(BlockFilter { id: 7, count: 1, include: true });
+        assert!(!filter.is_empty());
+        assert!(filter.matches(&robot));
+        filter.materials.push(MaterialFilter { id: 2, include: false });
+        assert!(!filter.matches(&robot));
+    }
+}
@@ -0,0 +1,335 @@
+//! Local full-text search over robot names and creator names.
+//!
+//! The index lives in memory and is kept in sync with the store, so it covers
+//! every robot crf_2b knows about. Searches support prefixes (`tank` finds
+//! `tanky`), typos (`tnak` finds `tank`) and `"quoted phrases"`, ranked by relevance.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix_web::{rt, web};
+use serde::{Deserialize, Serialize};
+use tantivy::collector::TopDocs;
+use tantivy::query::{BooleanQuery, BoostQuery, FuzzyTermQuery, Occur, PhraseQuery, Query, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption, Schema, STORED, STRING, TEXT};
+use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, Term};
+
+use crate::model::ResultItem;
+use crate::store::{RobotNames, Store};
+
+/// Most hits considered for one search, before filtering and paging
+pub const MAX_HITS: usize = 1000;
+
+/// Byte ranges of a robot's name and creator name that matched the search
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Highlights {
+    pub name: Vec<(usize, usize)>,
+    #[serde(rename = "creatorName")]
+    pub creator_name: Vec<(usize, usize)>,
+}
+
+/// Part of a search: a word, or words that must appear together
+enum Clause {
+    Word(String),
+    Phrase(Vec<String>),
+}
+
+/// Lowercased words with their byte ranges, split like tantivy's default tokenizer
+fn words(text: &str) -> Vec<(usize, usize, String)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (start, c.is_alphanumeric()) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                words.push((s, i, text[s..i].to_lowercase()));
+                start = None;
+            },
+            _ => {},
+        }
+    }
+    words
+}
+
+fn parse(text: &str) -> Vec<Clause> {
+    let mut clauses = Vec::new();
+    for (i, part) in text.split('"').enumerate() {
+        let part_words: Vec<String> = words(part).into_iter().map(|(_, _, w)| w).collect();
+        // odd parts are between quotes
+        if i % 2 == 1 && part_words.len() > 1 {
+            clauses.push(Clause::Phrase(part_words));
+        } else {
+            clauses.extend(part_words.into_iter().map(Clause::Word));
+        }
+    }
+    clauses
+}
+
+/// Typos allowed in a word; short words have to be exact
+fn fuzzy_distance(word: &str) -> u8 {
+    match word.chars().count() {
+        0..=3 => 0,
+        4..=6 => 1,
+        _ => 2,
+    }
+}
+
+/// Edits to turn `a` into `b`, counting swapping two neighbouring characters as one
+/// like the index's fuzzy queries do
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut before: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == cb { 0 } else { 1 };
+            let mut distance = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+            if i > 0 && j > 0 && *ca == b[j - 1] && a[i - 1] == *cb {
+                distance = distance.min(before[j - 1] + 1);
+            }
+            current.push(distance);
+        }
+        before = std::mem::replace(&mut previous, current);
+    }
+    previous[b.len()]
+}
+
+/// Ranges of `text` with a word matching one of `search` like the index would
+fn highlight(text: &str, search: &[String]) -> Vec<(usize, usize)> {
+    words(text).into_iter()
+        .filter(|(_, _, word)| search.iter().any(|s| {
+            word.starts_with(s.as_str()) || edit_distance(word, s) <= fuzzy_distance(s) as usize
+        }))
+        .map(|(start, end, _)| (start, end))
+        .collect()
+}
+
+pub struct FullTextIndex {
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    id: Field,
+    name: Field,
+    creator: Field,
+}
+
+impl FullTextIndex {
+    pub fn new() -> tantivy::Result<Self> {
+        let mut schema = Schema::builder();
+        let id = schema.add_text_field("id", STRING | STORED);
+        let name = schema.add_text_field("name", TEXT);
+        let creator = schema.add_text_field("creator", TEXT);
+        let index = Index::create_in_ram(schema.build());
+        let reader = index.reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()?;
+        let writer = index.writer_with_num_threads(1, 50_000_000)?;
+        Ok(Self {
+            reader,
+            writer: Mutex::new(writer),
+            id,
+            name,
+            creator,
+        })
+    }
+
+    /// Add or replace robots
+    pub fn index(&self, robots: &[RobotNames]) -> tantivy::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        for (id, name, creator) in robots {
+            writer.delete_term(Term::from_field_text(self.id, id));
+            writer.add_document(doc!(
+                self.id => id.as_str(),
+                self.name => name.as_str(),
+                self.creator => creator.as_str(),
+            ))?;
+        }
+        writer.commit()?;
+        self.reader.reload()
+    }
+
+    /// Keep the index up to date with robots (re)seen by the store, every `interval_secs`
+    pub fn spawn_sync(self: &Arc<Self>, store: Arc<Store>, interval_secs: u64) {
+        let index = self.clone();
+        rt::spawn(async move {
+            let mut since = 0;
+            loop {
+                let (target, source) = (index.clone(), store.clone());
+                let synced = web::block(move || -> Result<Option<i64>, String> {
+                    let (robots, newest) = source.robot_names_seen_since(since).map_err(|e| e.to_string())?;
+                    if !robots.is_empty() {
+                        target.index(&robots).map_err(|e| e.to_string())?;
+                        println!("Full-text index updated with {} robots", robots.len());
+                    }
+                    Ok(newest)
+                }).await;
+                match synced {
+                    Ok(Ok(Some(newest))) => since = newest,
+                    Ok(Ok(None)) => {},
+                    Ok(Err(e)) => println!("Full-text index sync failed: {}", e),
+                    Err(e) => println!("Full-text index sync failed: {}", e),
+                }
+                rt::time::sleep(Duration::from_secs(interval_secs)).await;
+            }
+        });
+    }
+
+    fn word_query(&self, word: &str) -> Box<dyn Query> {
+        let mut options: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        for field in [self.name, self.creator] {
+            let term = Term::from_field_text(field, word);
+            options.push((Occur::Should, Box::new(BoostQuery::new(Box::new(TermQuery::new(term.clone(), IndexRecordOption::WithFreqs)), 3.0))));
+            options.push((Occur::Should, Box::new(BoostQuery::new(Box::new(FuzzyTermQuery::new_prefix(term.clone(), 0, true)), 2.0))));
+            let distance = fuzzy_distance(word);
+            if distance > 0 {
+                options.push((Occur::Should, Box::new(FuzzyTermQuery::new(term, distance, true))));
+            }
+        }
+        Box::new(BooleanQuery::new(options))
+    }
+
+    fn phrase_query(&self, words: &[String]) -> Box<dyn Query> {
+        let options: Vec<(Occur, Box<dyn Query>)> = [self.name, self.creator].iter()
+            .map(|field| {
+                let terms = words.iter().map(|w| Term::from_field_text(*field, w)).collect();
+                (Occur::Should, Box::new(PhraseQuery::new(terms)) as Box<dyn Query>)
+            })
+            .collect();
+        Box::new(BoostQuery::new(Box::new(BooleanQuery::new(options)), 2.0))
+    }
+
+    /// IDs of the best matching robots, best first, and false if there were more than
+    /// [`MAX_HITS`] to return. Every word or phrase has to match.
+    pub fn search(&self, text: &str) -> tantivy::Result<(Vec<String>, bool)> {
+        let clauses: Vec<(Occur, Box<dyn Query>)> = parse(text).iter()
+            .map(|clause| match clause {
+                Clause::Word(word) => (Occur::Must, self.word_query(word)),
+                Clause::Phrase(words) => (Occur::Must, self.phrase_query(words)),
+            })
+            .collect();
+        if clauses.is_empty() {
+            return Ok((Vec::new(), true));
+        }
+        let searcher = self.reader.searcher();
+        // one extra to tell whether any were left out
+        let mut hits = searcher.search(&BooleanQuery::new(clauses), &TopDocs::with_limit(MAX_HITS + 1))?;
+        let complete = hits.len() <= MAX_HITS;
+        hits.truncate(MAX_HITS);
+        let mut ids = Vec::with_capacity(hits.len());
+        for (_score, address) in hits {
+            let doc = searcher.doc(address)?;
+            if let Some(id) = doc.get_first(self.id).and_then(|v| v.as_text()) {
+                ids.push(id.to_owned());
+            }
+        }
+        Ok((ids, complete))
+    }
+
+    /// Where a robot matched a search
+    pub fn highlights(text: &str, item: &ResultItem) -> Highlights {
+        let search: Vec<String> = parse(text).into_iter()
+            .flat_map(|clause| match clause {
+                Clause::Word(word) => vec![word],
+                Clause::Phrase(words) => words,
+            })
+            .collect();
+        Highlights {
+            name: highlight(&item.robot.name, &search),
+            creator_name: highlight(&item.robot.creatorName, &search),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clauses(text: &str) -> Vec<Vec<String>> {
+        parse(text).into_iter()
+            .map(|clause| match clause {
+                Clause::Word(word) => vec![word],
+                Clause::Phrase(words) => words,
+            })
+            .collect()
+    }
+
+    fn names(robots: &[(&str, &str, &str)]) -> Vec<RobotNames> {
+        robots.iter().map(|(id, name, creator)| (id.to_string(), name.to_string(), creator.to_string())).collect()
+    }
+
+    #[test]
+    fn parse_words_and_phrases() {
+        assert_eq!(clauses("Big  Tank"), [["big"], ["tank"]]);
+        assert_eq!(clauses("\"big tank\" mk2"), vec![vec!["big", "tank"], vec!["mk2"]]);
+        // a one-word phrase is just a word, and an unclosed quote still counts
+        assert_eq!(clauses("\"tank\" \"big ship"), vec![vec!["tank"], vec!["big", "ship"]]);
+        assert!(clauses(" \"\" -- ").is_empty());
+    }
+
+    #[test]
+    fn fuzzy_distance_grows_with_length() {
+        assert_eq!(fuzzy_distance("car"), 0);
+        assert_eq!(fuzzy_distance("tank"), 1);
+        assert_eq!(fuzzy_distance("rocket"), 1);
+        assert_eq!(fuzzy_distance("flamethrower"), 2);
+        // characters, not bytes
+        assert_eq!(fuzzy_distance("äöü"), 0);
+    }
+
+    #[test]
+    fn edit_distance_counts_edits() {
+        assert_eq!(edit_distance("tank", "tank"), 0);
+        assert_eq!(edit_distance("tank", "tanks"), 1);
+        assert_eq!(edit_distance("tnak", "tank"), 1);
+        assert_eq!(edit_distance("ab", "ba"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn highlight_prefixes_and_typos() {
+        let search = vec!["tank".to_owned(), "mk".to_owned()];
+        assert_eq!(highlight("Big Tanky MK2", &search), [(4, 9), (10, 13)]);
+        assert_eq!(highlight("tamk Tnak", &search), [(0, 4), (5, 9)]);
+        assert!(highlight("ship", &search).is_empty());
+        // ranges are bytes
+        assert_eq!(highlight("Ünder tank", &search), [(7, 11)]);
+    }
+
+    #[test]
+    fn search_ranks_and_reports_completeness() {
+        let index = FullTextIndex::new().unwrap();
+        index.index(&names(&[
+            ("1", "Big Tank", "Alice"),
+            ("2", "Tanky", "Bob"),
+            ("3", "Tnak Attack", "Carol"),
+            ("4", "Plane", "Tank Builder"),
+        ])).unwrap();
+        let (ids, complete) = index.search("tank").unwrap();
+        assert!(complete);
+        // exact words first, then the prefix match, then the typo
+        let mut exact = ids[..2].to_vec();
+        exact.sort();
+        assert_eq!(exact, ["1", "4"]);
+        assert_eq!(ids[2..], ["2", "3"]);
+        let (ids, _) = index.search("\"big tank\"").unwrap();
+        assert_eq!(ids, ["1"]);
+        let (ids, _) = index.search("plane builder").unwrap();
+        assert_eq!(ids, ["4"]);
+        assert_eq!(index.search("").unwrap(), (Vec::new(), true));
+    }
+
+    #[test]
+    fn search_stops_at_max_hits() {
+        let index = FullTextIndex::new().unwrap();
+        let robots: Vec<RobotNames> = (0..=MAX_HITS).map(|i| (i.to_string(), format!("Tank {}", i), "Alice".to_owned())).collect();
+        index.index(&robots).unwrap();
+        let (ids, complete) = index.search("tank").unwrap();
+        assert_eq!(ids.len(), MAX_HITS);
+        assert!(!complete);
+        // re-indexing replaces rather than duplicates
+        index.index(&robots[..1]).unwrap();
+        assert_eq!(index.search("alice").unwrap().0.len(), MAX_HITS);
+    }
+}
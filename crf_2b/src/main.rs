@@ -1,15 +1,43 @@
+mod auth;
+mod cache;
+mod catalogue;
+mod coalesce;
+mod config;
+mod crawler;
+mod creators;
+mod error;
+mod filter;
+mod fulltext;
+mod model;
+mod query;
+mod robots;
+mod search;
+mod store;
+
 use std::sync::Arc;
 
-use actix_web::{get, post, web, App, HttpServer, http::StatusCode};
+use actix_web::{get, post, web, App, HttpServer};
 use actix_web::{
     body::BoxBody, http::header::ContentType, HttpRequest, HttpResponse, Responder,
 };
 use actix_files::NamedFile;
+use clap::Parser;
+use crf_common::Catalogue;
 
-use libfj::robocraft2::{FactoryAPI, PortalTokenProvider, SearchResponse, SearchPayload, FactoryError};
+use auth::TokenManager;
+use cache::{SearchCache, CacheStatus, CACHE_HEADER};
+use error::{ApiError, ErrorCode, RequestId, REQUEST_ID_HEADER};
+use model::ResultItem;
+use query::SearchQuery;
+use robots::RobotIndex;
+use search::SearchService;
+use store::Store;
 
 struct SearchResultsResponder {
-    results: SearchResponse,
+    body: web::Bytes,
+    /// Unset for results that didn't come from upstream
+    cache: Option<CacheStatus>,
+    request_id: RequestId,
 }
 
 // Responder
@@ -17,103 +45,298 @@ impl Responder for SearchResultsResponder {
     type Body = BoxBody;
 
     fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
-        let body = serde_json::to_string(&self.results).unwrap();
-
         // Create response and set content type
-        HttpResponse::Ok()
-            .content_type(ContentType::json())
-            .body(body)
+        let mut response = HttpResponse::Ok();
+        response.content_type(ContentType::json())
+            .insert_header((REQUEST_ID_HEADER, self.request_id.0));
+        if let Some(cache) = self.cache {
+            response.insert_header((CACHE_HEADER, cache.as_str()));
+        }
+        response.body(self.body)
     }
 }
 
-#[post("/crf-api/search")]
-async fn crf_search_post(query: web::Json<SearchPayload>, data: web::Data<Arc<FactoryAPI>>) -> impl Responder {
-    match data.search((&*query).clone()).await {
-        Ok(results) => {
-            HttpResponse::Ok()
-                .content_type(ContentType::json())
-                .body(serde_json::to_string(&results).unwrap())
-        },
-        Err(FactoryError::Protocol(e)) => {
-            println!("Search error: {}", e);
-            HttpResponse::InternalServerError()
-                .finish()
-        },
-        Err(FactoryError::Response(e)) => {
-            println!("Search error: {} ({})", e.error_message, e.error);
-            HttpResponse::BadRequest()
-                .content_type(ContentType::json())
-                .body(serde_json::to_string(&e).unwrap())
-        },
-        Err(FactoryError::ResponseCode(e, status)) => {
-            println!("Search error: {} (status:{})", e, status);
-            actix_web::HttpResponseBuilder::new(StatusCode::from_u16(status).unwrap())
-                .body(e.to_string())
+async fn search(query: SearchQuery, request_id: RequestId, service: &Arc<SearchService>) -> Result<SearchResultsResponder, ApiError> {
+    let problems = query.validate();
+    if !problems.is_empty() {
+        return Err(ApiError::invalid_fields(problems).with_request_id(&request_id));
+    }
+    match service.search(&query).await {
+        Ok((body, cache)) => Ok(SearchResultsResponder { body, cache, request_id }),
+        Err(e) => {
+            let error = e.with_request_id(&request_id);
+            println!("Search error: {}", error);
+            Err(error)
         }
     }
 }
 
+#[post("/crf-api/search")]
+async fn crf_search_post(query: web::Json<SearchQuery>, request_id: RequestId, service: web::Data<Arc<SearchService>>) -> Result<SearchResultsResponder, ApiError> {
+    search(query.into_inner(), request_id, &service).await
+}
+
 #[get("/crf-api/search")]
-async fn crf_search_get(query: web::Query<SearchPayload>, data: web::Data<Arc<FactoryAPI>>) -> impl Responder {
-    match data.search((&*query).clone()).await {
-        Ok(results) => {
-            HttpResponse::Ok()
-                .content_type(ContentType::json())
-                .body(serde_json::to_string(&results).unwrap())
-        },
-        Err(FactoryError::Protocol(e)) => {
-            println!("Search error: {}", e);
-            HttpResponse::InternalServerError()
-                .finish()
+async fn crf_search_get(query: web::Query<SearchQuery>, request_id: RequestId, service: web::Data<Arc<SearchService>>) -> Result<SearchResultsResponder, ApiError> {
+    search(query.into_inner(), request_id, &service).await
+}
+
+
+/// A robot, with when it was seen if it came from the store
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RobotResponse {
+    #[serde(flatten)]
+    item: ResultItem,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    first_seen: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_seen: Option<i64>,
+}
+
+#[get("/crf-api/robot/{id}")]
+async fn crf_robot_get(id: web::Path<String>, request_id: RequestId, service: web::Data<Arc<SearchService>>) -> Result<web::Json<RobotResponse>, ApiError> {
+    let indexed = service.robots.get(&id);
+    // the store knows when it was seen, even when the index has the robot
+    let stored = match service.store.clone() {
+        Some(store) => {
+            let lookup = id.clone();
+            match web::block(move || store.robot(&lookup)).await {
+                Ok(Ok(stored)) => stored,
+                Ok(Err(e)) => {
+                    println!("Robot lookup error: {}", e);
+                    None
+                },
+                Err(e) => {
+                    println!("Robot lookup error: {}", e);
+                    None
+                },
+            }
         },
-        Err(FactoryError::Response(e)) => {
-            println!("Search error: {} ({})", e.error_message, e.error);
-            HttpResponse::BadRequest()
-                .content_type(ContentType::json())
-                .body(serde_json::to_string(&e).unwrap())
+        None => None,
+    };
+    let (first_seen, last_seen) = match &stored {
+        Some(stored) => (Some(stored.first_seen), Some(stored.last_seen)),
+        None => (None, None),
+    };
+    match indexed.or_else(|| stored.map(|stored| stored.item)) {
+        Some(item) => Ok(web::Json(RobotResponse { item, first_seen, last_seen })),
+        None => Err(ApiError::new(ErrorCode::NotFound, format!("Robot {} not found", id)).with_request_id(&request_id)),
+    }
+}
+
+/// Most history points served for one robot
+const HISTORY_LIMIT: usize = 1000;
+
+#[get("/crf-api/robot/{id}/history")]
+async fn crf_robot_history_get(id: web::Path<String>, request_id: RequestId, service: web::Data<Arc<SearchService>>) -> Result<web::Json<Vec<store::HistoryPoint>>, ApiError> {
+    let store = match service.store.clone() {
+        Some(store) => store,
+        None => return Err(ApiError::new(ErrorCode::NotFound, "The robot store is disabled").with_request_id(&request_id)),
+    };
+    let lookup = id.clone();
+    match web::block(move || store.history(&lookup, HISTORY_LIMIT)).await {
+        Ok(Ok(points)) if points.is_empty() => Err(ApiError::new(ErrorCode::NotFound, format!("No history for robot {}", id)).with_request_id(&request_id)),
+        Ok(Ok(points)) => Ok(web::Json(points)),
+        Ok(Err(e)) => Err(ApiError::new(ErrorCode::StoreError, e.to_string()).with_request_id(&request_id)),
+        Err(e) => Err(ApiError::new(ErrorCode::StoreError, e.to_string()).with_request_id(&request_id)),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct CreatorsQuery {
+    prefix: String,
+    limit: Option<usize>,
+}
+
+/// Creators the store would consider before ranking
+const CREATOR_CANDIDATES: usize = 200;
+
+#[get("/crf-api/creators")]
+async fn crf_creators_get(query: web::Query<CreatorsQuery>, request_id: RequestId, service: web::Data<Arc<SearchService>>) -> Result<web::Json<Vec<creators::CreatorSuggestion>>, ApiError> {
+    let prefix = query.prefix.trim().to_owned();
+    let limit = query.limit.unwrap_or(creators::DEFAULT_SUGGESTIONS).min(creators::MAX_SUGGESTIONS);
+    if prefix.is_empty() {
+        return Ok(web::Json(Vec::new()));
+    }
+    let candidates = match service.store.clone() {
+        Some(store) => {
+            let lookup = prefix.clone();
+            match web::block(move || store.creators(&lookup, CREATOR_CANDIDATES)).await {
+                Ok(Ok(candidates)) => candidates,
+                Ok(Err(e)) => return Err(ApiError::new(ErrorCode::StoreError, e.to_string()).with_request_id(&request_id)),
+                Err(e) => return Err(ApiError::new(ErrorCode::StoreError, e.to_string()).with_request_id(&request_id)),
+            }
         },
-        Err(FactoryError::ResponseCode(e, status)) => {
-            println!("Search error: {} (status:{})", e, status);
-            actix_web::HttpResponseBuilder::new(StatusCode::from_u16(status).unwrap())
-                .body(e.to_string())
-        }
+        None => service.robots.creators(&prefix),
+    };
+    Ok(web::Json(creators::rank(&prefix, candidates, limit)))
+}
+
+#[get("/crf-api/creator/{id}")]
+async fn crf_creator_get(id: web::Path<String>, request_id: RequestId, service: web::Data<Arc<SearchService>>) -> Result<web::Json<creators::CreatorSeen>, ApiError> {
+    let store = match service.store.clone() {
+        Some(store) => store,
+        None => return Err(ApiError::new(ErrorCode::NotFound, "The robot store is disabled").with_request_id(&request_id)),
+    };
+    let lookup = id.clone();
+    match web::block(move || store.creator_seen(&lookup)).await {
+        Ok(Ok(Some(seen))) => Ok(web::Json(seen)),
+        Ok(Ok(None)) => Err(ApiError::new(ErrorCode::NotFound, format!("No robots by creator {} in the store", id)).with_request_id(&request_id)),
+        Ok(Err(e)) => Err(ApiError::new(ErrorCode::StoreError, e.to_string()).with_request_id(&request_id)),
+        Err(e) => Err(ApiError::new(ErrorCode::StoreError, e.to_string()).with_request_id(&request_id)),
     }
 }
 
+#[get("/crf-api/catalogue")]
+async fn crf_catalogue_get(catalogue: web::Data<Arc<Catalogue>>) -> HttpResponse {
+    HttpResponse::Ok().json(catalogue.as_ref().as_ref())
+}
+
+#[get("/crf-api/crawls")]
+async fn crf_crawls_get(request_id: RequestId, service: web::Data<Arc<SearchService>>) -> Result<web::Json<Vec<store::CrawlRun>>, ApiError> {
+    let store = match service.store.clone() {
+        Some(store) => store,
+        None => return Err(ApiError::new(ErrorCode::NotFound, "The robot store is disabled").with_request_id(&request_id)),
+    };
+    match web::block(move || store.recent_crawls(20)).await {
+        Ok(Ok(runs)) => Ok(web::Json(runs)),
+        Ok(Err(e)) => Err(ApiError::new(ErrorCode::StoreError, e.to_string()).with_request_id(&request_id)),
+        Err(e) => Err(ApiError::new(ErrorCode::StoreError, e.to_string()).with_request_id(&request_id)),
+    }
+}
 
 #[get("/hello/{name}")]
 async fn greet(name: web::Path<String>) -> impl Responder {
     format!("Hello {name}!")
 }
 
-async fn index(_req: HttpRequest) -> actix_web::Result<NamedFile> {
-    Ok(NamedFile::open("../dist/index.html")?)
+async fn index(_req: HttpRequest, config: web::Data<config::Config>) -> actix_web::Result<NamedFile> {
+    Ok(NamedFile::open(config.static_root.join("index.html"))?)
 }
 
-async fn root_level(req: HttpRequest) -> actix_web::Result<NamedFile> {
+async fn root_level(req: HttpRequest, config: web::Data<config::Config>) -> actix_web::Result<NamedFile> {
     let path: std::path::PathBuf = req.match_info().query("filename").parse()?;
-    let redirect = std::path::Path::new("../dist").join(path);
+    let redirect = config.static_root.join(&path);
     println!("redirect path: {}", redirect.display());
-    Ok(NamedFile::open(redirect)?)
+    match NamedFile::open(redirect) {
+        Ok(file) => Ok(file),
+        // front-end routes like /robot/{id} are handled by the app in index.html
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && !path.starts_with("crf-api") && path.extension().is_none() => {
+            Ok(NamedFile::open(config.static_root.join("index.html"))?)
+        },
+        Err(e) => Err(e.into()),
+    }
 }
 
 #[actix_web::main] // or #[tokio::main]
 async fn main() -> std::io::Result<()> {
-    let factory_api = Arc::new(FactoryAPI::with_auth(
-        Box::new(PortalTokenProvider::with_username("FJAPIC00L", "P4$$w0rd")
-            .await.unwrap())));
-    HttpServer::new(move || {
+    let cli = config::Cli::parse();
+    let config = match config::Config::load(&cli) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    if cli.print_config {
+        print!("{}", config.to_toml());
+        return Ok(());
+    }
+    if let Err(e) = config.validate() {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+    let password = match config.credentials.password() {
+        Ok(password) => password,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    // the site works without names, so a missing catalogue isn't worth refusing to start over
+    let catalogue = if !config.catalogue.is_file() {
+        println!("Warning: catalogue {} not found, serving an empty one", config.catalogue.display());
+        Arc::new(Catalogue::default())
+    } else {
+        match catalogue::load(&config.catalogue) {
+            Ok(catalogue) => Arc::new(catalogue),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            }
+        }
+    };
+    println!("Catalogue has {} blocks and {} materials", catalogue.blocks.len(), catalogue.materials.len());
+    let store = if config.store.enabled {
+        match Store::open(&config.store.path) {
+            Ok(store) => Some(Arc::new(store)),
+            Err(e) => {
+                eprintln!("Cannot open {}: {}", config.store.path.display(), e);
+                std::process::exit(2);
+            }
+        }
+    } else {
+        None
+    };
+    let fulltext = match (&store, config.fulltext.enabled) {
+        (Some(store), true) => match fulltext::FullTextIndex::new() {
+            Ok(index) => {
+                let index = Arc::new(index);
+                index.spawn_sync(store.clone(), config.fulltext.sync_secs);
+                Some(index)
+            },
+            Err(e) => {
+                eprintln!("Cannot create full-text index: {}", e);
+                std::process::exit(2);
+            }
+        },
+        _ => None,
+    };
+    let token_manager = TokenManager::new(config.credentials.username().to_owned(), password, config.auth.clone());
+    token_manager.spawn_refresh();
+    if let (true, Some(store)) = (config.crawler.enabled, &store) {
+        crawler::Crawler::new(token_manager.clone(), store.clone(), config.crawler.clone()).spawn();
+    }
+    let search_service = SearchService::new(
+        token_manager,
+        SearchCache::new(config.cache.clone()),
+        RobotIndex::new(config.index.max_robots),
+        store,
+        fulltext,
+        config.filter.clone(),
+        config.offline.clone(),
+    );
+    let config = web::Data::new(config);
+    let app_config = config.clone();
+    let mut server = HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(factory_api.clone()))
+            .app_data(web::Data::new(search_service.clone()))
+            .app_data(web::Data::new(catalogue.clone()))
+            .app_data(web::JsonConfig::default().error_handler(ApiError::invalid_query))
+            .app_data(web::QueryConfig::default().error_handler(ApiError::invalid_query))
+            .app_data(app_config.clone())
             .route("/hello", web::get().to(|| async { "Hello World!" }))
             .route("/", web::get().to(index))
-            .route("/{filename:.*}", web::get().to(root_level))
             //.service(actix_files::Files::new("/{filename:.*}", "../dist"))
             .service(greet)
             .service(crf_search_get)
             .service(crf_search_post)
-    })
-    .bind(("127.0.0.1", 45554))?
-    .run()
-    .await
+            .service(crf_robot_get)
+            .service(crf_robot_history_get)
+            .service(crf_creators_get)
+            .service(crf_creator_get)
+            .service(crf_catalogue_get)
+            .service(crf_crawls_get)
+            // must be last, it matches everything
+            .route("/{filename:.*}", web::get().to(root_level))
+    });
+    if let Some(workers) = config.workers {
+        server = server.workers(workers);
+    }
+    for addr in config.bind.iter() {
+        println!("Listening on {}", addr);
+        server = server.bind(addr.as_str())?;
+    }
+    server.run().await
 }
@@ -0,0 +1,118 @@
+//! Search results as the front-end sees them.
+//!
+//! These mirror crf_tyew's `api` types; anything else upstream sends is kept in
+//! `extra` so results served from here look the same as proxied ones.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fulltext::Highlights;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SearchResults {
+    pub results: Vec<ResultItem>,
+    /// Set when crf_2b filtered the results itself
+    #[serde(rename = "postFilter", default, skip_serializing_if = "Option::is_none")]
+    pub post_filter: Option<PostFilterInfo>,
+    /// Where the results came from; always set by crf_2b
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<ResultSource>,
+    /// For results from the store, when it was last updated (unix seconds)
+    #[serde(rename = "archivedAt", default, skip_serializing_if = "Option::is_none")]
+    pub archived_at: Option<i64>,
+    /// Set for full-text searches
+    #[serde(rename = "fullText", default, skip_serializing_if = "Option::is_none")]
+    pub full_text: Option<FullTextInfo>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultSource {
+    /// The CRF, directly or through the response cache
+    Upstream,
+    /// crf_2b's own robot store, because upstream couldn't answer
+    Cache,
+    /// crf_2b's full-text index of the store, which upstream has no equivalent of
+    Index,
+}
+
+/// How many robots a full-text search matched, before filtering and paging
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct FullTextInfo {
+    /// Best matches that were filtered and paged
+    pub hits: usize,
+    /// False when there were more matches than that, which were left out
+    pub complete: bool,
+}
+
+/// How much of upstream was looked through to answer a post-filtered search
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PostFilterInfo {
+    pub scanned_pages: usize,
+    pub scanned_robots: usize,
+    /// False when the scan limit was reached before finding enough matches
+    pub complete: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ResultItem {
+    pub robot: Robot,
+    pub prices: Vec<Price>,
+    /// Where a full-text search matched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub highlights: Option<Highlights>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Price {
+    pub currency: usize,
+    pub amount: usize,
+}
+
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Robot {
+    pub id: String,
+    pub name: String,
+    pub creatorId: String,
+    pub creatorName: String,
+    pub image: String,
+    pub baseCpu: usize,
+    pub weaponCpu: usize,
+    pub cosmeticCpu: usize,
+    pub clusterCount: usize,
+    pub blockCounts: HashMap<usize, usize>,
+    pub materialsUsed: Vec<usize>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A robot with only its ID, name and creator set, for tests to fill in
+#[cfg(test)]
+pub fn test_robot(id: &str, creator_id: &str, creator_name: &str) -> ResultItem {
+    ResultItem {
+        robot: Robot {
+            id: id.to_owned(),
+            name: format!("Robot {}", id),
+            creatorId: creator_id.to_owned(),
+            creatorName: creator_name.to_owned(),
+            image: String::new(),
+            baseCpu: 0,
+            weaponCpu: 0,
+            cosmeticCpu: 0,
+            clusterCount: 0,
+            blockCounts: HashMap::new(),
+            materialsUsed: Vec::new(),
+            extra: serde_json::Map::new(),
+        },
+        prices: Vec::new(),
+        highlights: None,
+        extra: serde_json::Map::new(),
+    }
+}
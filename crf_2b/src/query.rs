@@ -0,0 +1,55 @@
+use crf_common::parse_id_counts;
+use libfj::robocraft2::SearchPayload;
+use serde_json::Value;
+
+use crate::filter::{BlockFilter, MaterialFilter, PostFilter, PriceFilter};
+
+pub use crf_common::{FieldError, MAX_COUNT, SORT_OPTIONS};
+
+/// Results per page when crf_2b pages results itself and the search doesn't say
+pub const DEFAULT_COUNT: usize = 25;
+
+/// A search as sent by the front-end, validated by the same code
+pub type SearchQuery = crf_common::SearchRequest;
+
+/// (count, robots skipped) for the query's page, with `count` clamped to [`MAX_COUNT`];
+/// `None` when the page is so deep the numbers overflow
+pub fn page_window(query: &SearchQuery) -> Option<(usize, usize)> {
+    let count = query.count.map(|c| (c.max(1) as usize).min(MAX_COUNT)).unwrap_or(DEFAULT_COUNT);
+    let page = query.page.map(|p| p.max(1) as usize).unwrap_or(1);
+    let skip = (page - 1).checked_mul(count)?;
+    skip.checked_add(count)?;
+    Some((count, skip))
+}
+
+/// The upstream search; libfj's payload uses the same JSON names, minus the fields crf_2b handles itself
+pub fn to_payload(query: &SearchQuery) -> Result<SearchPayload, serde_json::Error> {
+    let mut value = serde_json::to_value(query)?;
+    if let Value::Object(fields) = &mut value {
+        for field in SearchQuery::LOCAL_FIELDS {
+            fields.remove(*field);
+        }
+    }
+    serde_json::from_value(value)
+}
+
+/// The parts of the search upstream doesn't support
+pub fn post_filter(query: &SearchQuery) -> PostFilter {
+    let has_price = query.price_minimum.is_some() || query.price_maximum.is_some() || query.price_currency.is_some();
+    let ids = |list: &Option<String>| list.as_deref().and_then(parse_id_counts).unwrap_or_default();
+    let blocks = ids(&query.blocks_included).into_iter()
+        .map(|(id, count)| BlockFilter { id, count, include: true })
+        .chain(ids(&query.blocks_excluded).into_iter().map(|(id, count)| BlockFilter { id, count, include: false }));
+    let materials = ids(&query.materials_included).into_iter()
+        .map(|(id, _)| MaterialFilter { id, include: true })
+        .chain(ids(&query.materials_excluded).into_iter().map(|(id, _)| MaterialFilter { id, include: false }));
+    PostFilter {
+        price: has_price.then(|| PriceFilter {
+            currency: query.price_currency,
+            minimum: query.price_minimum.unwrap_or(0).max(0) as usize,
+            maximum: query.price_maximum.map(|max| max.max(0) as usize),
+        }),
+        blocks: blocks.collect(),
+        materials: materials.collect(),
+    }
+}
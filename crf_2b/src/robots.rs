@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::creators::CreatorSuggestion;
+use crate::model::ResultItem;
+
+struct Seen {
+    item: ResultItem,
+    order: u64,
+}
+
+#[derive(Default)]
+struct IndexInner {
+    robots: HashMap<String, Seen>,
+    clock: u64,
+}
+
+/// Every robot seen in a search response, by ID.
+///
+/// libfj 0.7.1's robocraft2 API has searches but no single-robot lookup, so crf_2b can't ask
+/// the CRF for one robot; `/crf-api/robot/{id}` answers from this index, then the store.
+/// The index is empty after a restart, so robot links only keep working with `store.enabled`.
+/// When full, the robots that were seen longest ago are dropped first.
+pub struct RobotIndex {
+    max_robots: usize,
+    inner: Mutex<IndexInner>,
+}
+
+impl RobotIndex {
+    pub fn new(max_robots: usize) -> Self {
+        Self {
+            max_robots,
+            inner: Mutex::new(IndexInner::default()),
+        }
+    }
+
+    /// Remember the robots in a search response
+    pub fn observe(&self, items: &[ResultItem]) {
+        let mut inner = self.inner.lock().unwrap();
+        for item in items.iter().cloned() {
+            inner.clock += 1;
+            let order = inner.clock;
+            inner.robots.insert(item.robot.id.clone(), Seen { item, order });
+        }
+        if inner.robots.len() > self.max_robots {
+            let mut orders: Vec<u64> = inner.robots.values().map(|seen| seen.order).collect();
+            let excess = orders.len() - self.max_robots;
+            let (_, cutoff, _) = orders.select_nth_unstable(excess - 1);
+            let cutoff = *cutoff;
+            inner.robots.retain(|_, seen| seen.order > cutoff);
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<ResultItem> {
+        self.inner.lock().unwrap()
+            .robots.get(id)
+            .map(|seen| seen.item.clone())
+    }
+
+    /// Creators of the robots in the index whose name or ID starts with `prefix` (ignoring case)
+    pub fn creators(&self, prefix: &str) -> Vec<CreatorSuggestion> {
+        let prefix = prefix.to_lowercase();
+        let inner = self.inner.lock().unwrap();
+        let mut creators: HashMap<&str, (CreatorSuggestion, u64)> = HashMap::new();
+        for seen in inner.robots.values() {
+            let robot = &seen.item.robot;
+            if !robot.creatorName.to_lowercase().starts_with(&prefix) && !robot.creatorId.to_lowercase().starts_with(&prefix) {
+                continue;
+            }
+            let (creator, newest) = creators.entry(robot.creatorId.as_str()).or_insert_with(|| (CreatorSuggestion {
+                id: robot.creatorId.clone(),
+                name: robot.creatorName.clone(),
+                robots: 0,
+            }, seen.order));
+            creator.robots += 1;
+            // keep the name they had most recently
+            if seen.order >= *newest {
+                creator.name = robot.creatorName.clone();
+                *newest = seen.order;
+            }
+        }
+        creators.into_values().map(|(creator, _)| creator).collect()
+    }
+}
@@ -0,0 +1,410 @@
+use std::sync::Arc;
+
+use actix_web::{rt, web, web::Bytes};
+use libfj::robocraft2::SearchPayload;
+
+use crate::auth::{TokenManager, UpstreamError};
+use crate::cache::{CacheStatus, Lookup, SearchCache};
+use crate::coalesce::SingleFlight;
+use crate::config::{FilterConfig, OfflineConfig, OfflineMode};
+use crate::error::{ApiError, ErrorCode};
+use crate::filter::PostFilter;
+use crate::fulltext::FullTextIndex;
+use crate::model::{FullTextInfo, PostFilterInfo, ResultItem, ResultSource, SearchResults};
+use crate::query::{self, post_filter, to_payload, SearchQuery};
+use crate::robots::RobotIndex;
+use crate::store::{self, Store};
+
+/// Upstream searches in progress, keyed like the cache
+type SearchFlight = SingleFlight<String, Result<Bytes, Arc<UpstreamError>>>;
+
+/// Everything between a search request and the CRF
+pub struct SearchService {
+    pub tokens: Arc<TokenManager>,
+    pub cache: SearchCache,
+    flight: SearchFlight,
+    pub robots: RobotIndex,
+    /// Persistent copy of everything seen, unless disabled
+    pub store: Option<Arc<Store>>,
+    /// Local full-text index over the store, unless disabled
+    pub fulltext: Option<Arc<FullTextIndex>>,
+    filter: FilterConfig,
+    offline: OfflineConfig,
+}
+
+impl SearchService {
+    pub fn new(tokens: Arc<TokenManager>, cache: SearchCache, robots: RobotIndex, store: Option<Arc<Store>>, fulltext: Option<Arc<FullTextIndex>>, filter: FilterConfig, offline: OfflineConfig) -> Arc<Self> {
+        Arc::new(Self {
+            tokens,
+            cache,
+            flight: SingleFlight::new(),
+            robots,
+            store,
+            fulltext,
+            filter,
+            offline,
+        })
+    }
+
+    /// Search upstream and remember what came back
+    async fn upstream(&self, payload: SearchPayload) -> Result<Bytes, UpstreamError> {
+        let response = self.tokens.search(payload).await?;
+        match serde_json::to_value(&response).and_then(serde_json::from_value::<SearchResults>) {
+            Ok(mut results) => {
+                results.source = Some(ResultSource::Upstream);
+                let body = Bytes::from(serde_json::to_vec(&results).unwrap());
+                self.remember(results);
+                Ok(body)
+            },
+            Err(e) => {
+                println!("Cannot index search results: {}", e);
+                Ok(Bytes::from(serde_json::to_vec(&response).unwrap()))
+            },
+        }
+    }
+
+    fn remember(&self, results: SearchResults) {
+        self.robots.observe(&results.results);
+        if let Some(store) = self.store.clone() {
+            let seen = store::now();
+            // the response doesn't need to wait for the disk
+            rt::spawn(async move {
+                match web::block(move || store.upsert(&results.results, seen, None)).await {
+                    Ok(Ok(_)) => {},
+                    Ok(Err(e)) => println!("Cannot store search results: {}", e),
+                    Err(e) => println!("Cannot store search results: {}", e),
+                }
+            });
+        }
+    }
+
+    /// Search through the response cache, refreshing stale entries in the background.
+    /// Identical searches that miss the cache at the same time share one upstream request.
+    pub async fn search_cached(self: &Arc<Self>, payload: SearchPayload) -> Result<(Bytes, CacheStatus), Arc<UpstreamError>> {
+        let key = SearchCache::key(&payload);
+        match self.cache.get(&key) {
+            Lookup::Fresh(body) => return Ok((body, CacheStatus::Hit)),
+            Lookup::Refreshing(body) => return Ok((body, CacheStatus::Stale)),
+            Lookup::Stale(body) => {
+                let service = self.clone();
+                rt::spawn(async move {
+                    match service.upstream(payload).await {
+                        Ok(body) => service.cache.insert(key, body),
+                        Err(_) => service.cache.refresh_failed(&key),
+                    }
+                });
+                return Ok((body, CacheStatus::Stale));
+            },
+            Lookup::Miss => {},
+        }
+        let body = self.flight.run(key.clone(), || async move {
+            let body = self.upstream(payload).await.map_err(Arc::new)?;
+            self.cache.insert(key, body.clone());
+            Ok(body)
+        }).await?;
+        Ok((body, CacheStatus::Miss))
+    }
+
+    /// Scan upstream pages of a search for the first `wanted` robots that pass `filter`.
+    /// Scanning stops at the configured page limit; the returned info says whether it got that far.
+    async fn scan(self: &Arc<Self>, query: &SearchQuery, filter: &PostFilter, wanted: usize) -> Result<(Vec<ResultItem>, PostFilterInfo, CacheStatus), ApiError> {
+        // `wanted` comes from the client; never reserve more than the scan could find
+        let mut results = Vec::with_capacity(wanted.min(self.filter.page_size.saturating_mul(self.filter.max_pages)));
+        let mut info = PostFilterInfo::default();
+        let mut status = CacheStatus::Hit;
+        for page in 1..=self.filter.max_pages {
+            let mut upstream_query = query.clone();
+            upstream_query.page = Some(page as isize);
+            upstream_query.count = Some(self.filter.page_size as isize);
+            let payload = to_payload(&upstream_query)
+                .map_err(|e| ApiError::new(ErrorCode::InvalidQuery, e.to_string()))?;
+            let (body, cache) = self.search_cached(payload).await
+                .map_err(|e| ApiError::from_upstream(&e))?;
+            status = status.max(cache);
+            let upstream: SearchResults = serde_json::from_slice(&body)
+                .map_err(|e| ApiError::new(ErrorCode::UpstreamError, format!("Cannot read CRF results: {}", e)))?;
+            info.scanned_pages += 1;
+            info.scanned_robots += upstream.results.len();
+            let last_page = upstream.results.len() < self.filter.page_size;
+            results.extend(upstream.results.into_iter().filter(|item| filter.matches(item)).take(wanted - results.len()));
+            if results.len() >= wanted || last_page {
+                info.complete = true;
+                break;
+            }
+        }
+        Ok((results, info, status))
+    }
+
+    fn filtered_body(results: Vec<ResultItem>, info: PostFilterInfo) -> Bytes {
+        let filtered = SearchResults {
+            results,
+            post_filter: Some(info),
+            source: Some(ResultSource::Upstream),
+            archived_at: None,
+            full_text: None,
+            extra: serde_json::Map::new(),
+        };
+        Bytes::from(serde_json::to_vec(&filtered).unwrap())
+    }
+
+    /// Search for robots upstream can't filter for, by scanning upstream pages and
+    /// keeping only what passes `filter`. `page` and `count` of the query apply to the
+    /// filtered results. Scanning stops at the configured page limit, which the
+    /// response's `postFilter` says.
+    pub async fn search_filtered(self: &Arc<Self>, query: &SearchQuery, filter: &PostFilter) -> Result<(Bytes, CacheStatus), ApiError> {
+        let (count, skip) = page_window(query)?;
+        let (results, info, status) = self.scan(query, filter, skip + count).await?;
+        let results = results.into_iter().skip(skip).collect();
+        Ok((Self::filtered_body(results, info), status))
+    }
+
+    /// Search for robots by any of several creators. Upstream only takes one creator,
+    /// so this searches once per creator (with the query's sort) and merges the results
+    /// before paging them; see [`merge`]. Each creator's search is scanned like
+    /// [`search_filtered`](Self::search_filtered), and `postFilter` covers all of them.
+    pub async fn search_creators(self: &Arc<Self>, query: &SearchQuery, creators: &[String], filter: &PostFilter) -> Result<(Bytes, CacheStatus), ApiError> {
+        let (count, skip) = page_window(query)?;
+        let mut lists = Vec::with_capacity(creators.len());
+        let mut info = PostFilterInfo { complete: true, ..PostFilterInfo::default() };
+        let mut status = CacheStatus::Hit;
+        for creator in creators {
+            let mut creator_query = query.clone();
+            creator_query.set_creators(std::slice::from_ref(creator));
+            let (results, scanned, cache) = self.scan(&creator_query, filter, skip + count).await?;
+            info.scanned_pages += scanned.scanned_pages;
+            info.scanned_robots += scanned.scanned_robots;
+            info.complete &= scanned.complete;
+            status = status.max(cache);
+            lists.push(results);
+        }
+        let results = merge(lists, &query.sort_by, query.order_by == "descending").into_iter()
+            .skip(skip)
+            .take(count)
+            .collect();
+        Ok((Self::filtered_body(results, info), status))
+    }
+
+    /// Search upstream, or the store when upstream is down (or always, if configured).
+    /// The cache status is `None` for answers from the store.
+    pub async fn search(self: &Arc<Self>, query: &SearchQuery) -> Result<(Bytes, Option<CacheStatus>), ApiError> {
+        if query.is_fulltext() {
+            return self.search_fulltext(query).await.map(|body| (body, None));
+        }
+        if self.offline.mode == OfflineMode::Always {
+            return self.search_store(query).await.map(|body| (body, None));
+        }
+        let filter = post_filter(query);
+        let creators = query.creators();
+        let result = if creators.len() > 1 {
+            self.search_creators(query, &creators, &filter).await
+        } else if filter.is_empty() {
+            // the one creator might only be in `creatorIds`, which upstream doesn't know
+            let mut query = query.clone();
+            query.set_creators(&creators);
+            let payload = to_payload(&query)
+                .map_err(|e| ApiError::new(ErrorCode::InvalidQuery, e.to_string()))?;
+            self.search_cached(payload).await
+                .map_err(|e| ApiError::from_upstream(&e))
+        } else {
+            let mut query = query.clone();
+            query.set_creators(&creators);
+            self.search_filtered(&query, &filter).await
+        };
+        match result {
+            Ok((body, cache)) => Ok((body, Some(cache))),
+            // a bad search would be just as bad offline
+            Err(e) if e.code == ErrorCode::InvalidQuery || e.code == ErrorCode::UpstreamRejected => Err(e),
+            Err(e) if self.offline.mode == OfflineMode::Fallback && self.store.is_some() => {
+                println!("Search falling back to the store: {}", e);
+                self.search_store(query).await.map(|body| (body, None))
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Answer a search from the store, with the same filters, sorts and pages as upstream
+    async fn search_store(&self, query: &SearchQuery) -> Result<Bytes, ApiError> {
+        let store = self.store.clone()
+            .ok_or_else(|| ApiError::new(ErrorCode::Unavailable, "The robot store is disabled"))?;
+        let query = query.clone();
+        let (results, archived_at) = web::block(move || -> rusqlite::Result<_> {
+            Ok((store.search(&query)?, store.last_updated()?))
+        }).await
+            .map_err(|e| ApiError::new(ErrorCode::StoreError, e.to_string()))?
+            .map_err(|e| ApiError::new(ErrorCode::StoreError, e.to_string()))?;
+        let results = SearchResults {
+            results,
+            post_filter: None,
+            source: Some(ResultSource::Cache),
+            archived_at,
+            full_text: None,
+            extra: serde_json::Map::new(),
+        };
+        Ok(Bytes::from(serde_json::to_vec(&results).unwrap()))
+    }
+
+    /// Rank the store's robots by how well their names match the text, with highlights.
+    /// Other filters and paging apply to the best [`MAX_HITS`](crate::fulltext::MAX_HITS) matches; the sort is always
+    /// by relevance. `fullText` says when there were more matches than that.
+    async fn search_fulltext(&self, query: &SearchQuery) -> Result<Bytes, ApiError> {
+        let (store, fulltext) = match (self.store.clone(), self.fulltext.clone()) {
+            (Some(store), Some(fulltext)) => (store, fulltext),
+            _ => return Err(ApiError::new(ErrorCode::Unavailable, "Full-text search is disabled")),
+        };
+        let (count, skip) = page_window(query)?;
+        let query = query.clone();
+        let (results, info) = web::block(move || -> Result<_, String> {
+            let text = query.text.clone().unwrap_or_default();
+            let (ids, complete) = fulltext.search(&text).map_err(|e| e.to_string())?;
+            let info = FullTextInfo { hits: ids.len(), complete };
+            let mut matches = store.filter_ids(&query, &ids).map_err(|e| e.to_string())?;
+            let rank: std::collections::HashMap<&str, usize> = ids.iter().enumerate()
+                .map(|(rank, id)| (id.as_str(), rank))
+                .collect();
+            matches.sort_by_key(|item| rank.get(item.robot.id.as_str()).copied().unwrap_or(usize::MAX));
+            let results = matches.into_iter()
+                .skip(skip)
+                .take(count)
+                .map(|mut item| {
+                    item.highlights = Some(FullTextIndex::highlights(&text, &item));
+                    item
+                })
+                .collect();
+            Ok((results, info))
+        }).await
+            .map_err(|e| ApiError::new(ErrorCode::StoreError, e.to_string()))?
+            .map_err(|e| ApiError::new(ErrorCode::StoreError, e))?;
+        // not a fallback for upstream being down, so not archived either
+        let results = SearchResults {
+            results,
+            post_filter: None,
+            source: Some(ResultSource::Index),
+            archived_at: None,
+            full_text: Some(info),
+            extra: serde_json::Map::new(),
+        };
+        Ok(Bytes::from(serde_json::to_vec(&results).unwrap()))
+    }
+}
+
+/// [`query::page_window`], refusing pages too deep to count to
+fn page_window(query: &SearchQuery) -> Result<(usize, usize), ApiError> {
+    query::page_window(query).ok_or_else(|| ApiError::new(ErrorCode::InvalidQuery, "page is too large"))
+}
+
+/// The value upstream sorts by for `sort_by`, where results carry it
+fn sort_value(sort_by: &str, item: &ResultItem) -> Option<u64> {
+    match sort_by {
+        "cpuPower" => Some(item.robot.baseCpu as u64),
+        "cpuWeapon" => Some(item.robot.weaponCpu as u64),
+        "cpuCosmetic" => Some(item.robot.cosmeticCpu as u64),
+        "clusterCount" => Some(item.robot.clusterCount as u64),
+        "price" => item.prices.iter().map(|p| p.amount as u64).min(),
+        "views" => item.extra.get("views").or_else(|| item.robot.extra.get("views")).and_then(|v| v.as_u64()),
+        // upload dates and the default order aren't in the results
+        _ => None,
+    }
+}
+
+/// Merge results of the same search for different creators, each already in upstream's order.
+///
+/// Lists are merged by the sort's value where results carry it, so the result is in the same
+/// order as one upstream search would have been. Where they don't (`default`, `date`), and on
+/// ties, the lists take turns, so each stays in upstream's order and none crowds out the rest.
+fn merge(lists: Vec<Vec<ResultItem>>, sort_by: &str, descending: bool) -> Vec<ResultItem> {
+    let mut lists: Vec<std::iter::Peekable<std::vec::IntoIter<ResultItem>>> = lists.into_iter()
+        .map(|list| list.into_iter().peekable())
+        .collect();
+    let mut taken = vec![0usize; lists.len()];
+    let mut merged = Vec::new();
+    loop {
+        let mut best: Option<(usize, Option<u64>)> = None;
+        for (index, list) in lists.iter_mut().enumerate() {
+            let value = match list.peek() {
+                Some(item) => sort_value(sort_by, item),
+                None => continue,
+            };
+            let better = match best {
+                None => true,
+                Some((best_index, best_value)) => match (value, best_value) {
+                    (Some(value), Some(best_value)) if value != best_value => (value < best_value) != descending,
+                    _ => taken[index] < taken[best_index],
+                },
+            };
+            if better {
+                best = Some((index, value));
+            }
+        }
+        match best {
+            Some((index, _)) => {
+                merged.push(lists[index].next().unwrap());
+                taken[index] += 1;
+            },
+            None => return merged,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{test_robot, Price};
+
+    fn item(id: &str, base_cpu: usize, prices: &[usize]) -> ResultItem {
+        let mut item = test_robot(id, "c", "C");
+        item.robot.baseCpu = base_cpu;
+        item.prices = prices.iter().map(|&amount| Price { currency: 0, amount }).collect();
+        item
+    }
+
+    fn ids(items: &[ResultItem]) -> Vec<&str> {
+        items.iter().map(|item| item.robot.id.as_str()).collect()
+    }
+
+    #[test]
+    fn merges_by_sort_value() {
+        let a = vec![item("a1", 1, &[]), item("a5", 5, &[]), item("a6", 6, &[])];
+        let b = vec![item("b2", 2, &[]), item("b3", 3, &[]), item("b9", 9, &[])];
+        assert_eq!(ids(&merge(vec![a, b], "cpuPower", false)), ["a1", "b2", "b3", "a5", "a6", "b9"]);
+    }
+
+    #[test]
+    fn merges_descending() {
+        let a = vec![item("a6", 6, &[]), item("a1", 1, &[])];
+        let b = vec![item("b9", 9, &[]), item("b3", 3, &[])];
+        assert_eq!(ids(&merge(vec![a, b], "cpuPower", true)), ["b9", "a6", "b3", "a1"]);
+    }
+
+    #[test]
+    fn takes_turns_without_sort_values() {
+        let a = vec![item("a1", 0, &[]), item("a2", 0, &[]), item("a3", 0, &[])];
+        let b = vec![item("b1", 0, &[])];
+        let c = vec![item("c1", 0, &[]), item("c2", 0, &[])];
+        assert_eq!(ids(&merge(vec![a, b, c], "date", false)), ["a1", "b1", "c1", "a2", "c2", "a3"]);
+    }
+
+    #[test]
+    fn ties_take_turns_and_keep_upstream_order() {
+        let a = vec![item("a1", 1, &[10]), item("a2", 1, &[20])];
+        let b = vec![item("b1", 1, &[5]), item("b2", 1, &[30])];
+        assert_eq!(ids(&merge(vec![a, b], "cpuPower", false)), ["a1", "b1", "a2", "b2"]);
+        let a = vec![item("a1", 0, &[10]), item("a2", 0, &[20])];
+        let b = vec![item("b1", 0, &[5]), item("b2", 0, &[30])];
+        assert_eq!(ids(&merge(vec![a, b], "price", false)), ["b1", "a1", "a2", "b2"]);
+    }
+
+    #[test]
+    fn page_windows_clamp_and_refuse_overflow() {
+        let window = |page, count| page_window(&SearchQuery { page, count, ..Default::default() }).ok();
+        assert_eq!(window(None, None), Some((25, 0)));
+        assert_eq!(window(Some(3), Some(10)), Some((10, 20)));
+        assert_eq!(window(Some(2), Some(isize::MAX)), Some((100, 100)));
+        assert_eq!(window(Some(isize::MAX), Some(100)), None);
+    }
+
+    #[test]
+    fn empty_lists() {
+        assert!(merge(Vec::new(), "default", false).is_empty());
+        assert_eq!(ids(&merge(vec![Vec::new(), vec![item("b1", 0, &[])]], "default", false)), ["b1"]);
+    }
+}
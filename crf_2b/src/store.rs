@@ -0,0 +1,980 @@
+//! Everything crf_2b has ever seen from the CRF, kept in SQLite.
+//!
+//! Every upstream search response is upserted here, so robots stay queryable
+//! when upstream can't answer (or can't answer the question at all).
+
+use std::fmt;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crf_common::parse_date;
+use rusqlite::{params, params_from_iter, types::Value, Connection, OptionalExtension, Transaction};
+use serde::Serialize;
+
+use crate::creators::{CreatorSeen, CreatorSuggestion};
+use crate::model::{Price, ResultItem, Robot};
+use crate::query::{page_window, post_filter, SearchQuery};
+
+/// Schema changes, in order. The database's `user_version` is how many have been applied;
+/// never edit one that's been released, add another instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: robots and their current prices
+    "CREATE TABLE robots (
+        id TEXT PRIMARY KEY NOT NULL,
+        name TEXT NOT NULL,
+        creator_id TEXT NOT NULL,
+        creator_name TEXT NOT NULL,
+        image TEXT NOT NULL,
+        base_cpu INTEGER NOT NULL,
+        weapon_cpu INTEGER NOT NULL,
+        cosmetic_cpu INTEGER NOT NULL,
+        cluster_count INTEGER NOT NULL,
+        block_counts TEXT NOT NULL, -- JSON object of block ID to count
+        materials_used TEXT NOT NULL, -- JSON array of material IDs
+        robot_extra TEXT NOT NULL, -- JSON object of robot fields we don't know about
+        item_extra TEXT NOT NULL, -- same, for the result item around the robot
+        first_seen INTEGER NOT NULL, -- unix seconds
+        last_seen INTEGER NOT NULL
+    );
+    CREATE INDEX robots_creator_id ON robots (creator_id);
+    CREATE INDEX robots_last_seen ON robots (last_seen);
+    CREATE TABLE prices (
+        robot_id TEXT NOT NULL REFERENCES robots (id) ON DELETE CASCADE,
+        currency INTEGER NOT NULL,
+        amount INTEGER NOT NULL,
+        PRIMARY KEY (robot_id, currency)
+    );",
+    // 2: crawler runs, and which run last saw each robot
+    "CREATE TABLE crawl_runs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        started INTEGER NOT NULL,
+        finished INTEGER, -- NULL until every sort has been paged through
+        sort_index INTEGER NOT NULL DEFAULT 0, -- where to resume
+        page INTEGER NOT NULL DEFAULT 1,
+        requests INTEGER NOT NULL DEFAULT 0,
+        robots_seen INTEGER NOT NULL DEFAULT 0,
+        added INTEGER NOT NULL DEFAULT 0,
+        changed INTEGER NOT NULL DEFAULT 0,
+        disappeared INTEGER NOT NULL DEFAULT 0
+    );
+    ALTER TABLE robots ADD COLUMN last_crawl INTEGER REFERENCES crawl_runs (id);
+    ALTER TABLE robots ADD COLUMN disappeared INTEGER; -- unix seconds when a complete crawl last missed it",
+    // 3: what robots looked like over time, seeded with what's stored now
+    "CREATE TABLE robot_history (
+        robot_id TEXT NOT NULL REFERENCES robots (id) ON DELETE CASCADE,
+        seen INTEGER NOT NULL, -- unix seconds
+        name TEXT NOT NULL,
+        base_cpu INTEGER NOT NULL,
+        weapon_cpu INTEGER NOT NULL,
+        cosmetic_cpu INTEGER NOT NULL,
+        cluster_count INTEGER NOT NULL,
+        views INTEGER, -- NULL when upstream didn't say
+        prices TEXT NOT NULL, -- JSON array of prices
+        PRIMARY KEY (robot_id, seen)
+    );
+    INSERT INTO robot_history (robot_id, seen, name, base_cpu, weapon_cpu, cosmetic_cpu, cluster_count, views, prices)
+    SELECT id, last_seen, name, base_cpu, weapon_cpu, cosmetic_cpu, cluster_count,
+        coalesce(json_extract(item_extra, '$.views'), json_extract(robot_extra, '$.views')),
+        coalesce((SELECT json_group_array(json_object('currency', currency, 'amount', amount)) FROM prices WHERE robot_id = robots.id), '[]')
+    FROM robots;",
+    // 4: crawls that may have missed robots, which can't tell what disappeared
+    "ALTER TABLE crawl_runs ADD COLUMN truncated INTEGER NOT NULL DEFAULT 0; -- 1 if a sort was cut short or the run paused
+    CREATE INDEX robots_last_crawl ON robots (last_crawl);",
+    // 5: pausing no longer truncates a crawl; count how often it carried on instead
+    "ALTER TABLE crawl_runs ADD COLUMN resumes INTEGER NOT NULL DEFAULT 0;",
+];
+
+const ROBOT_COLUMNS: &str = "id, name, creator_id, creator_name, image, base_cpu, weapon_cpu, cosmetic_cpu,
+    cluster_count, block_counts, materials_used, robot_extra, item_extra, first_seen, last_seen";
+
+/// What an upsert did to the stored robots
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UpsertCounts {
+    pub added: usize,
+    pub changed: usize,
+}
+
+enum Upserted {
+    Added,
+    Changed,
+    Unchanged,
+}
+
+/// One crawl through the CRF, and how far it got
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CrawlRun {
+    pub id: i64,
+    pub started: i64,
+    pub finished: Option<i64>,
+    pub sort_index: usize,
+    pub page: usize,
+    pub requests: usize,
+    /// Different robots seen, however many sorts they turned up in
+    pub robots_seen: usize,
+    pub added: usize,
+    pub changed: usize,
+    pub disappeared: usize,
+    /// Upstream stopped a sort early, so robots it didn't see aren't marked disappeared
+    pub truncated: bool,
+    /// Times the run carried on after running out of budget, failing or a restart
+    pub resumes: usize,
+}
+
+const CRAWL_COLUMNS: &str = "id, started, finished, sort_index, page, requests, robots_seen, added, changed, disappeared, truncated, resumes";
+
+const HISTORY_COLUMNS: &str = "seen, name, base_cpu, weapon_cpu, cosmetic_cpu, cluster_count, views, prices";
+
+/// A robot's tracked values at one point in time.
+/// A point is only recorded when something in it changed since the last one.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryPoint {
+    pub seen: i64,
+    pub name: String,
+    pub base_cpu: usize,
+    pub weapon_cpu: usize,
+    pub cosmetic_cpu: usize,
+    pub cluster_count: usize,
+    pub views: Option<u64>,
+    pub prices: Vec<Price>,
+}
+
+impl HistoryPoint {
+    fn of(item: &ResultItem, seen: i64) -> Self {
+        let robot = &item.robot;
+        let mut prices = item.prices.clone();
+        prices.sort_by_key(|price| price.currency);
+        Self {
+            seen,
+            name: robot.name.clone(),
+            base_cpu: robot.baseCpu,
+            weapon_cpu: robot.weaponCpu,
+            cosmetic_cpu: robot.cosmeticCpu,
+            cluster_count: robot.clusterCount,
+            // upstream sorts by views but doesn't document where it puts them
+            views: item.extra.get("views")
+                .or_else(|| robot.extra.get("views"))
+                .and_then(|views| views.as_u64()),
+            prices,
+        }
+    }
+
+    /// Same values, whenever they were seen
+    fn same_values(&self, other: &Self) -> bool {
+        Self { seen: other.seen, ..self.clone() } == *other
+    }
+}
+
+/// (id, name, creator name) of a robot, which is all the full-text index needs
+pub type RobotNames = (String, String, String);
+
+/// A robot as stored, with when it was seen
+#[derive(Clone, Debug)]
+pub struct StoredRobot {
+    pub item: ResultItem,
+    pub first_seen: i64,
+    pub last_seen: i64,
+}
+
+pub struct Store {
+    conn: Mutex<Connection>,
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    Sqlite(rusqlite::Error),
+    /// The database was written by a newer crf_2b
+    TooNew(i64),
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Sqlite(e)
+    }
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sqlite(e) => write!(f, "Database error: {}", e),
+            Self::TooNew(version) => write!(f, "Database schema version {} is newer than this build supports ({})", version, MIGRATIONS.len()),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+pub fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+/// Add a query parameter, returning its placeholder
+fn bind(args: &mut Vec<Value>, value: impl Into<Value>) -> String {
+    args.push(value.into());
+    format!("?{}", args.len())
+}
+
+/// `text` with `LIKE` wildcards escaped, for `ESCAPE '\'`
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// `LIKE` pattern matching `text` anywhere, with wildcards in it escaped
+fn like_pattern(text: &str) -> String {
+    format!("%{}%", escape_like(text))
+}
+
+/// `LIKE` pattern matching text starting with `prefix`
+fn prefix_pattern(prefix: &str) -> String {
+    format!("{}%", escape_like(prefix))
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap()
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(column: usize, text: &str) -> rusqlite::Result<T> {
+    serde_json::from_str(text)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(column, rusqlite::types::Type::Text, Box::new(e)))
+}
+
+impl Store {
+    /// Open (or create) the database and bring its schema up to date
+    pub fn open(path: &Path) -> Result<Self, StoreError> {
+        let mut conn = Connection::open(path)?;
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA foreign_keys = ON;")?;
+        Self::migrate(&mut conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn migrate(conn: &mut Connection) -> Result<(), StoreError> {
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > MIGRATIONS.len() as i64 {
+            return Err(StoreError::TooNew(version));
+        }
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            println!("Migrating database to schema version {}", index + 1);
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", (index + 1) as i64)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Insert or update robots seen in one search response at `seen`,
+    /// by crawl run `crawl` if it was the crawler that saw them.
+    /// Robots stored from a newer response keep that response's values.
+    pub fn upsert(&self, items: &[ResultItem], seen: i64, crawl: Option<i64>) -> rusqlite::Result<UpsertCounts> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut counts = UpsertCounts::default();
+        for item in items {
+            match Self::upsert_one(&tx, item, seen, crawl)? {
+                Upserted::Added => counts.added += 1,
+                Upserted::Changed => counts.changed += 1,
+                Upserted::Unchanged => {},
+            }
+        }
+        tx.commit()?;
+        Ok(counts)
+    }
+
+    fn upsert_one(tx: &Transaction, item: &ResultItem, seen: i64, crawl: Option<i64>) -> rusqlite::Result<Upserted> {
+        let robot = &item.robot;
+        let existing = tx.query_row(
+            &format!("SELECT {} FROM robots WHERE id = ?1", ROBOT_COLUMNS),
+            params![robot.id],
+            Self::read_robot,
+        ).optional()?;
+        let upserted = match existing {
+            None => Upserted::Added,
+            // a response older than what's stored arrived late; it still shows the robot was there
+            Some(old) if old.last_seen > seen => {
+                tx.prepare_cached("UPDATE robots SET last_crawl = coalesce(?2, last_crawl), disappeared = NULL WHERE id = ?1")?
+                    .execute(params![robot.id, crawl])?;
+                return Ok(Upserted::Unchanged);
+            },
+            Some(mut old) => {
+                old.item.prices = Self::prices(tx, &robot.id)?;
+                let mut new = item.clone();
+                new.prices.sort_by_key(|price| price.currency);
+                if old.item == new { Upserted::Unchanged } else { Upserted::Changed }
+            },
+        };
+        tx.prepare_cached(
+            "INSERT INTO robots (id, name, creator_id, creator_name, image, base_cpu, weapon_cpu, cosmetic_cpu,
+                cluster_count, block_counts, materials_used, robot_extra, item_extra, first_seen, last_seen, last_crawl)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?14, ?15)
+            ON CONFLICT (id) DO UPDATE SET
+                name = excluded.name,
+                creator_id = excluded.creator_id,
+                creator_name = excluded.creator_name,
+                image = excluded.image,
+                base_cpu = excluded.base_cpu,
+                weapon_cpu = excluded.weapon_cpu,
+                cosmetic_cpu = excluded.cosmetic_cpu,
+                cluster_count = excluded.cluster_count,
+                block_counts = excluded.block_counts,
+                materials_used = excluded.materials_used,
+                robot_extra = excluded.robot_extra,
+                item_extra = excluded.item_extra,
+                last_seen = max(last_seen, excluded.last_seen),
+                last_crawl = coalesce(excluded.last_crawl, last_crawl),
+                disappeared = NULL"
+        )?.execute(params![
+            robot.id,
+            robot.name,
+            robot.creatorId,
+            robot.creatorName,
+            robot.image,
+            robot.baseCpu as i64,
+            robot.weaponCpu as i64,
+            robot.cosmeticCpu as i64,
+            robot.clusterCount as i64,
+            to_json(&robot.blockCounts),
+            to_json(&robot.materialsUsed),
+            to_json(&robot.extra),
+            to_json(&item.extra),
+            seen,
+            crawl,
+        ])?;
+        tx.prepare_cached("DELETE FROM prices WHERE robot_id = ?1")?
+            .execute(params![robot.id])?;
+        let mut insert_price = tx.prepare_cached("INSERT OR REPLACE INTO prices (robot_id, currency, amount) VALUES (?1, ?2, ?3)")?;
+        for price in item.prices.iter() {
+            insert_price.execute(params![robot.id, price.currency as i64, price.amount as i64])?;
+        }
+        Self::record_history(tx, item, seen)?;
+        Ok(upserted)
+    }
+
+    /// Add a history point for the robot, unless nothing tracked changed since the last one
+    fn record_history(tx: &Transaction, item: &ResultItem, seen: i64) -> rusqlite::Result<()> {
+        let point = HistoryPoint::of(item, seen);
+        let last = tx.prepare_cached(&format!("SELECT {} FROM robot_history WHERE robot_id = ?1 ORDER BY seen DESC LIMIT 1", HISTORY_COLUMNS))?
+            .query_row(params![item.robot.id], Self::read_history)
+            .optional()?;
+        if last.map(|last| last.same_values(&point) || last.seen > seen).unwrap_or(false) {
+            return Ok(());
+        }
+        tx.prepare_cached(
+            "INSERT OR REPLACE INTO robot_history (robot_id, seen, name, base_cpu, weapon_cpu, cosmetic_cpu, cluster_count, views, prices)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"
+        )?.execute(params![
+            item.robot.id,
+            point.seen,
+            point.name,
+            point.base_cpu as i64,
+            point.weapon_cpu as i64,
+            point.cosmetic_cpu as i64,
+            point.cluster_count as i64,
+            point.views.map(|views| views as i64),
+            to_json(&point.prices),
+        ])?;
+        Ok(())
+    }
+
+    /// The robot's most recent `limit` history points, oldest first
+    pub fn history(&self, id: &str, limit: usize) -> rusqlite::Result<Vec<HistoryPoint>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare_cached(&format!(
+            "SELECT {} FROM (SELECT * FROM robot_history WHERE robot_id = ?1 ORDER BY seen DESC LIMIT ?2) ORDER BY seen ASC",
+            HISTORY_COLUMNS,
+        ))?;
+        let points = statement.query_map(params![id, limit as i64], Self::read_history)?.collect();
+        points
+    }
+
+    /// Row of `robot_history` in [`HISTORY_COLUMNS`] order
+    fn read_history(row: &rusqlite::Row) -> rusqlite::Result<HistoryPoint> {
+        Ok(HistoryPoint {
+            seen: row.get(0)?,
+            name: row.get(1)?,
+            base_cpu: row.get::<_, i64>(2)? as usize,
+            weapon_cpu: row.get::<_, i64>(3)? as usize,
+            cosmetic_cpu: row.get::<_, i64>(4)? as usize,
+            cluster_count: row.get::<_, i64>(5)? as usize,
+            views: row.get::<_, Option<i64>>(6)?.map(|views| views as u64),
+            prices: from_json(7, &row.get::<_, String>(7)?)?,
+        })
+    }
+
+    pub fn robot(&self, id: &str) -> rusqlite::Result<Option<StoredRobot>> {
+        let conn = self.conn.lock().unwrap();
+        let stored = conn.query_row(
+            &format!("SELECT {} FROM robots WHERE id = ?1", ROBOT_COLUMNS),
+            params![id],
+            Self::read_robot,
+        ).optional()?;
+        let mut stored = match stored {
+            Some(stored) => stored,
+            None => return Ok(None),
+        };
+        stored.item.prices = Self::prices(&conn, id)?;
+        Ok(Some(stored))
+    }
+
+    fn prices(conn: &Connection, id: &str) -> rusqlite::Result<Vec<Price>> {
+        let mut statement = conn.prepare_cached("SELECT currency, amount FROM prices WHERE robot_id = ?1 ORDER BY currency")?;
+        let prices = statement.query_map(params![id], |row| Ok(Price {
+            currency: row.get::<_, i64>(0)? as usize,
+            amount: row.get::<_, i64>(1)? as usize,
+        }))?.collect();
+        prices
+    }
+
+    /// Row of `robots` in [`ROBOT_COLUMNS`] order; prices are left empty
+    fn read_robot(row: &rusqlite::Row) -> rusqlite::Result<StoredRobot> {
+        Ok(StoredRobot {
+            item: ResultItem {
+                robot: Robot {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    creatorId: row.get(2)?,
+                    creatorName: row.get(3)?,
+                    image: row.get(4)?,
+                    baseCpu: row.get::<_, i64>(5)? as usize,
+                    weaponCpu: row.get::<_, i64>(6)? as usize,
+                    cosmeticCpu: row.get::<_, i64>(7)? as usize,
+                    clusterCount: row.get::<_, i64>(8)? as usize,
+                    blockCounts: from_json(9, &row.get::<_, String>(9)?)?,
+                    materialsUsed: from_json(10, &row.get::<_, String>(10)?)?,
+                    extra: from_json(11, &row.get::<_, String>(11)?)?,
+                },
+                prices: Vec::new(),
+                highlights: None,
+                extra: from_json(12, &row.get::<_, String>(12)?)?,
+            },
+            first_seen: row.get(13)?,
+            last_seen: row.get(14)?,
+        })
+    }
+
+    /// The crawl that was interrupted (or ran out of budget) before finishing, if any
+    pub fn unfinished_crawl(&self) -> rusqlite::Result<Option<CrawlRun>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            &format!("SELECT {} FROM crawl_runs WHERE finished IS NULL ORDER BY id DESC LIMIT 1", CRAWL_COLUMNS),
+            [],
+            Self::read_crawl,
+        ).optional()
+    }
+
+    /// [`unfinished_crawl`](Self::unfinished_crawl), counting that it's carrying on
+    pub fn resume_crawl(&self) -> rusqlite::Result<Option<CrawlRun>> {
+        let mut run = match self.unfinished_crawl()? {
+            Some(run) => run,
+            None => return Ok(None),
+        };
+        run.resumes += 1;
+        let conn = self.conn.lock().unwrap();
+        conn.execute("UPDATE crawl_runs SET resumes = ?2 WHERE id = ?1", params![run.id, run.resumes as i64])?;
+        Ok(Some(run))
+    }
+
+    pub fn recent_crawls(&self, limit: usize) -> rusqlite::Result<Vec<CrawlRun>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare_cached(&format!("SELECT {} FROM crawl_runs ORDER BY id DESC LIMIT ?1", CRAWL_COLUMNS))?;
+        let runs = statement.query_map(params![limit as i64], Self::read_crawl)?.collect();
+        runs
+    }
+
+    pub fn start_crawl(&self, started: i64) -> rusqlite::Result<CrawlRun> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("INSERT INTO crawl_runs (started) VALUES (?1)", params![started])?;
+        conn.query_row(
+            &format!("SELECT {} FROM crawl_runs WHERE id = ?1", CRAWL_COLUMNS),
+            params![conn.last_insert_rowid()],
+            Self::read_crawl,
+        )
+    }
+
+    /// Save how far a crawl has got, so it can resume from there.
+    /// `robots_seen` is counted from the robots the run has upserted, not taken from `run`.
+    pub fn save_crawl(&self, run: &CrawlRun) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE crawl_runs SET sort_index = ?2, page = ?3, requests = ?4, added = ?5, changed = ?6, truncated = ?7,
+                robots_seen = (SELECT count(*) FROM robots WHERE last_crawl = ?1)
+            WHERE id = ?1",
+            params![run.id, run.sort_index as i64, run.page as i64, run.requests as i64, run.added as i64, run.changed as i64, run.truncated],
+        )?;
+        Ok(())
+    }
+
+    /// Mark a crawl complete. Robots an earlier crawl saw but this one didn't have disappeared,
+    /// unless this one was truncated and may simply have missed them.
+    ///
+    /// A run that paused still counts: pages may have shifted while it waited, but a robot
+    /// that only moved between pages of one sort is still seen by the others, so one that
+    /// no sort turned up is gone.
+    pub fn finish_crawl(&self, run: &mut CrawlRun, finished: i64) -> rusqlite::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let disappeared = if run.truncated {
+            0
+        } else {
+            tx.execute(
+                "UPDATE robots SET disappeared = ?2 WHERE last_crawl < ?1 AND disappeared IS NULL",
+                params![run.id, finished],
+            )?
+        };
+        let robots_seen: i64 = tx.query_row("SELECT count(*) FROM robots WHERE last_crawl = ?1", params![run.id], |row| row.get(0))?;
+        tx.execute(
+            "UPDATE crawl_runs SET finished = ?2, disappeared = ?3, robots_seen = ?4, truncated = ?5 WHERE id = ?1",
+            params![run.id, finished, disappeared as i64, robots_seen, run.truncated],
+        )?;
+        tx.commit()?;
+        run.finished = Some(finished);
+        run.disappeared = disappeared;
+        run.robots_seen = robots_seen as usize;
+        Ok(())
+    }
+
+    /// Row of `crawl_runs` in [`CRAWL_COLUMNS`] order
+    fn read_crawl(row: &rusqlite::Row) -> rusqlite::Result<CrawlRun> {
+        Ok(CrawlRun {
+            id: row.get(0)?,
+            started: row.get(1)?,
+            finished: row.get(2)?,
+            sort_index: row.get::<_, i64>(3)? as usize,
+            page: row.get::<_, i64>(4)? as usize,
+            requests: row.get::<_, i64>(5)? as usize,
+            robots_seen: row.get::<_, i64>(6)? as usize,
+            added: row.get::<_, i64>(7)? as usize,
+            changed: row.get::<_, i64>(8)? as usize,
+            disappeared: row.get::<_, i64>(9)? as usize,
+            truncated: row.get(10)?,
+            resumes: row.get::<_, i64>(11)? as usize,
+        })
+    }
+
+    /// When the newest robot in the store was seen
+    pub fn last_updated(&self) -> rusqlite::Result<Option<i64>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row("SELECT max(last_seen) FROM robots", [], |row| row.get(0))
+    }
+
+    /// `WHERE` conditions for everything a search filters on, with their parameters
+    fn conditions(query: &SearchQuery) -> (Vec<String>, Vec<Value>) {
+        let mut args = Vec::new();
+        let mut conditions = vec!["disappeared IS NULL".to_owned()];
+        if let Some(text) = query.text.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+            let pattern = bind(&mut args, like_pattern(text));
+            conditions.push(format!("(name LIKE {0} ESCAPE '\\' OR creator_name LIKE {0} ESCAPE '\\')", pattern));
+        }
+        let ranges = [
+            ("base_cpu", query.base_minimum_cpu, query.base_maximum_cpu),
+            ("weapon_cpu", query.weapon_minimum_cpu, query.weapon_maximum_cpu),
+            ("cosmetic_cpu", query.cosmetic_minimum_cpu, query.cosmetic_maximum_cpu),
+            ("cluster_count", query.cluster_minimum, query.cluster_maximum),
+        ];
+        for (column, min, max) in ranges {
+            if let Some(min) = min {
+                conditions.push(format!("{} >= {}", column, bind(&mut args, min as i64)));
+            }
+            if let Some(max) = max {
+                conditions.push(format!("{} <= {}", column, bind(&mut args, max as i64)));
+            }
+        }
+        if let Some(date) = query.date_minimum.as_deref().and_then(parse_date) {
+            conditions.push(format!("first_seen >= {}", bind(&mut args, date)));
+        }
+        if let Some(date) = query.date_maximum.as_deref().and_then(parse_date) {
+            conditions.push(format!("first_seen <= {}", bind(&mut args, date)));
+        }
+        let creators: Vec<String> = query.creators().iter().map(|c| c.to_lowercase()).collect();
+        if !creators.is_empty() {
+            let ids = bind(&mut args, to_json(&creators));
+            conditions.push(format!("lower(creator_id) IN (SELECT value FROM json_each({}))", ids));
+        }
+        let filter = post_filter(query);
+        if let Some(price) = &filter.price {
+            let mut in_range = format!("p.amount >= {}", bind(&mut args, price.minimum as i64));
+            if let Some(max) = price.maximum {
+                in_range += &format!(" AND p.amount <= {}", bind(&mut args, max as i64));
+            }
+            if let Some(currency) = price.currency {
+                in_range += &format!(" AND p.currency = {}", bind(&mut args, currency as i64));
+            }
+            // robots without prices aren't for sale, so never match
+            conditions.push(format!("EXISTS (SELECT 1 FROM prices p WHERE p.robot_id = robots.id AND {})", in_range));
+        }
+        for block in filter.blocks.iter() {
+            let path = bind(&mut args, format!("$.\"{}\"", block.id));
+            let count = bind(&mut args, block.count as i64);
+            let comparison = if block.include { ">=" } else { "<" };
+            conditions.push(format!("coalesce(json_extract(block_counts, {}), 0) {} {}", path, comparison, count));
+        }
+        for material in filter.materials.iter() {
+            let id = bind(&mut args, material.id as i64);
+            let not = if material.include { "" } else { "NOT " };
+            conditions.push(format!("{}EXISTS (SELECT 1 FROM json_each(materials_used) WHERE value = {})", not, id));
+        }
+        (conditions, args)
+    }
+
+    /// Search stored robots like upstream would, including the filters upstream can't do.
+    ///
+    /// The store doesn't know when robots were uploaded or how often they were viewed,
+    /// so date filters and the `date` sort use when a robot was first seen, and the
+    /// `default` and `views` sorts are by first seen too. Robots the crawler found
+    /// to have disappeared are left out.
+    pub fn search(&self, query: &SearchQuery) -> rusqlite::Result<Vec<ResultItem>> {
+        let (conditions, mut args) = Self::conditions(query);
+        let sort = match query.sort_by.as_str() {
+            "cpuPower" => "base_cpu",
+            "cpuWeapon" => "weapon_cpu",
+            "cpuCosmetic" => "cosmetic_cpu",
+            // robots not for sale go last either way, not first as if they were free
+            "price" => "(SELECT min(p.amount) FROM prices p WHERE p.robot_id = robots.id) IS NULL, (SELECT min(p.amount) FROM prices p WHERE p.robot_id = robots.id)",
+            "clusterCount" => "cluster_count",
+            _ => "first_seen",
+        };
+        let order = if query.order_by == "descending" { "DESC" } else { "ASC" };
+        // a page too deep to count to is past the end
+        let (count, offset) = match page_window(query).and_then(|(count, skip)| Some((count as i64, i64::try_from(skip).ok()?))) {
+            Some(window) => window,
+            None => return Ok(Vec::new()),
+        };
+        let sql = format!(
+            "SELECT {} FROM robots WHERE {} ORDER BY {} {}, id ASC LIMIT {} OFFSET {}",
+            ROBOT_COLUMNS,
+            conditions.join(" AND "),
+            sort,
+            order,
+            bind(&mut args, count),
+            bind(&mut args, offset),
+        );
+        self.load(&sql, &args)
+    }
+
+    /// Which of `ids` pass the search's filters, in no particular order.
+    /// The text, sorting and paging are ignored; they're the full-text index's job.
+    pub fn filter_ids(&self, query: &SearchQuery, ids: &[String]) -> rusqlite::Result<Vec<ResultItem>> {
+        let query = SearchQuery { text: None, ..query.clone() };
+        let (mut conditions, mut args) = Self::conditions(&query);
+        conditions.push(format!("id IN (SELECT value FROM json_each({}))", bind(&mut args, to_json(&ids))));
+        let sql = format!("SELECT {} FROM robots WHERE {}", ROBOT_COLUMNS, conditions.join(" AND "));
+        self.load(&sql, &args)
+    }
+
+    /// Robots (with prices) selected by `sql`, which has to select [`ROBOT_COLUMNS`]
+    fn load(&self, sql: &str, args: &[Value]) -> rusqlite::Result<Vec<ResultItem>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(sql)?;
+        let robots: Vec<StoredRobot> = statement.query_map(params_from_iter(args.iter()), Self::read_robot)?
+            .collect::<rusqlite::Result<_>>()?;
+        robots.into_iter()
+            .map(|mut stored| {
+                stored.item.prices = Self::prices(&conn, &stored.item.robot.id)?;
+                Ok(stored.item)
+            })
+            .collect()
+    }
+
+    /// Robots seen at or after `since`, and the newest `last_seen` among them
+    pub fn robot_names_seen_since(&self, since: i64) -> rusqlite::Result<(Vec<RobotNames>, Option<i64>)> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare_cached("SELECT id, name, creator_name, last_seen FROM robots WHERE last_seen >= ?1")?;
+        let mut newest = None;
+        let mut robots = Vec::new();
+        let mut rows = statement.query(params![since])?;
+        while let Some(row) = rows.next()? {
+            let last_seen: i64 = row.get(3)?;
+            newest = newest.max(Some(last_seen));
+            robots.push((row.get(0)?, row.get(1)?, row.get(2)?));
+        }
+        Ok((robots, newest))
+    }
+
+    /// When the creator's robots were first seen, if the store has any of them.
+    /// Disappeared robots count too; they were still seen. IDs match ignoring case, like searches.
+    pub fn creator_seen(&self, id: &str) -> rusqlite::Result<Option<CreatorSeen>> {
+        let conn = self.conn.lock().unwrap();
+        let (robots, first_seen, newest_seen): (i64, Option<i64>, Option<i64>) = conn.query_row(
+            "SELECT count(*), min(first_seen), max(first_seen) FROM robots WHERE creator_id = ?1 COLLATE NOCASE",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        Ok(first_seen.zip(newest_seen).map(|(first_seen, newest_seen)| CreatorSeen {
+            robots: robots as usize,
+            first_seen,
+            newest_seen,
+        }))
+    }
+
+    /// Up to `limit` creators whose name or ID starts with `prefix` (ignoring case), best first
+    /// like [`creators::rank`](crate::creators::rank) orders them, so the limit doesn't cut off
+    /// exact matches of creators with few robots.
+    pub fn creators(&self, prefix: &str, limit: usize) -> rusqlite::Result<Vec<CreatorSuggestion>> {
+        let conn = self.conn.lock().unwrap();
+        // SQLite takes the bare creator_name from the row with max(last_seen)
+        let mut statement = conn.prepare_cached(
+            "SELECT creator_id, creator_name, count(*), max(last_seen) FROM robots
+            WHERE creator_name LIKE ?1 ESCAPE '\\' OR creator_id LIKE ?1 ESCAPE '\\'
+            GROUP BY creator_id
+            ORDER BY
+                CASE
+                    WHEN lower(creator_name) = lower(?3) THEN 0
+                    WHEN substr(creator_name, 1, length(?3)) = ?3 THEN 1
+                    ELSE 2
+                END,
+                count(*) DESC, length(creator_name), creator_name
+            LIMIT ?2"
+        )?;
+        let creators = statement.query_map(params![prefix_pattern(prefix), limit as i64, prefix], |row| Ok(CreatorSuggestion {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            robots: row.get::<_, i64>(2)? as usize,
+        }))?.collect();
+        creators
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_robot;
+
+    fn memory() -> Store {
+        Store::open(Path::new(":memory:")).unwrap()
+    }
+
+    #[test]
+    fn creators_rank_exact_names_before_the_limit() {
+        let store = memory();
+        let mut items: Vec<ResultItem> = (0..5).map(|i| test_robot(&format!("p{}", i), "prolific", "Bobbington")).collect();
+        items.extend((0..3).map(|i| test_robot(&format!("m{}", i), "middling", "bobcat")));
+        items.push(test_robot("e0", "exact", "Bob"));
+        store.upsert(&items, 100, None).unwrap();
+        let names = |prefix: &str, limit: usize| -> Vec<String> {
+            store.creators(prefix, limit).unwrap().into_iter().map(|c| c.name).collect()
+        };
+        assert_eq!(names("bob", 1), ["Bob"]);
+        assert_eq!(names("Bob", 3), ["Bob", "Bobbington", "bobcat"]);
+        assert_eq!(names("bobc", 3), ["bobcat"]);
+        assert_eq!(names("mid", 3), ["bobcat"]);
+        assert!(names("%", 3).is_empty());
+    }
+
+    /// IDs of the robots a search would still find
+    fn listed(store: &Store) -> Vec<String> {
+        let mut ids: Vec<String> = store.search(&SearchQuery::default()).unwrap().into_iter().map(|item| item.robot.id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn crawls_count_each_robot_once() {
+        let store = memory();
+        let mut run = store.start_crawl(100).unwrap();
+        let items = vec![test_robot("a", "c", "C"), test_robot("b", "c", "C")];
+        // the same robots turning up in every sort
+        for _ in 0..3 {
+            store.upsert(&items, 100, Some(run.id)).unwrap();
+        }
+        store.save_crawl(&run).unwrap();
+        assert_eq!(store.unfinished_crawl().unwrap().unwrap().robots_seen, 2);
+        store.finish_crawl(&mut run, 200).unwrap();
+        assert_eq!(run.robots_seen, 2);
+        assert_eq!(store.recent_crawls(1).unwrap()[0].robots_seen, 2);
+    }
+
+    #[test]
+    fn complete_crawls_mark_missing_robots_disappeared() {
+        let store = memory();
+        let mut first = store.start_crawl(100).unwrap();
+        store.upsert(&[test_robot("a", "c", "C"), test_robot("b", "c", "C")], 100, Some(first.id)).unwrap();
+        store.finish_crawl(&mut first, 150).unwrap();
+        let mut second = store.start_crawl(200).unwrap();
+        store.upsert(&[test_robot("a", "c", "C")], 200, Some(second.id)).unwrap();
+        store.finish_crawl(&mut second, 250).unwrap();
+        assert_eq!(second.disappeared, 1);
+        assert_eq!(listed(&store), ["a"]);
+    }
+
+    #[test]
+    fn resumed_crawls_mark_missing_robots_disappeared() {
+        let store = memory();
+        let mut first = store.start_crawl(100).unwrap();
+        store.upsert(&[test_robot("a", "c", "C"), test_robot("b", "c", "C"), test_robot("c", "c", "C")], 100, Some(first.id)).unwrap();
+        store.finish_crawl(&mut first, 150).unwrap();
+        // out of budget after the first page
+        let mut second = store.start_crawl(200).unwrap();
+        store.upsert(&[test_robot("a", "c", "C")], 200, Some(second.id)).unwrap();
+        second.page = 2;
+        store.save_crawl(&second).unwrap();
+        // carrying on next interval
+        let mut second = store.resume_crawl().unwrap().unwrap();
+        assert_eq!((second.id, second.page, second.resumes), (2, 2, 1));
+        assert!(!second.truncated);
+        store.upsert(&[test_robot("b", "c", "C")], 300, Some(second.id)).unwrap();
+        store.finish_crawl(&mut second, 350).unwrap();
+        assert_eq!(second.disappeared, 1);
+        assert_eq!(second.robots_seen, 2);
+        assert_eq!(store.recent_crawls(1).unwrap()[0].resumes, 1);
+        assert_eq!(listed(&store), ["a", "b"]);
+        assert!(store.resume_crawl().unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated_crawls_leave_missing_robots_alone() {
+        let store = memory();
+        let mut first = store.start_crawl(100).unwrap();
+        store.upsert(&[test_robot("a", "c", "C"), test_robot("b", "c", "C")], 100, Some(first.id)).unwrap();
+        store.finish_crawl(&mut first, 150).unwrap();
+        let mut second = store.start_crawl(200).unwrap();
+        store.upsert(&[test_robot("a", "c", "C")], 200, Some(second.id)).unwrap();
+        second.truncated = true;
+        store.save_crawl(&second).unwrap();
+        // resuming reads the flag back
+        let mut second = store.unfinished_crawl().unwrap().unwrap();
+        assert!(second.truncated);
+        store.finish_crawl(&mut second, 250).unwrap();
+        assert_eq!(second.disappeared, 0);
+        assert!(store.recent_crawls(1).unwrap()[0].truncated);
+        assert_eq!(listed(&store), ["a", "b"]);
+    }
+
+    #[test]
+    fn price_filters_skip_robots_not_for_sale() {
+        let store = memory();
+        let mut free = test_robot("free", "c", "C");
+        free.prices.push(Price { currency: 0, amount: 0 });
+        let mut dear = test_robot("dear", "c", "C");
+        dear.prices.push(Price { currency: 0, amount: 500 });
+        store.upsert(&[free, dear, test_robot("unlisted", "c", "C")], 100, None).unwrap();
+        let ids = |query: SearchQuery| -> Vec<String> {
+            store.search(&query).unwrap().into_iter().map(|item| item.robot.id).collect()
+        };
+        assert_eq!(ids(SearchQuery { price_maximum: Some(0), ..SearchQuery::default() }), ["free"]);
+        assert_eq!(ids(SearchQuery { price_minimum: Some(100), ..SearchQuery::default() }), ["dear"]);
+        assert_eq!(ids(SearchQuery { price_currency: Some(0), ..SearchQuery::default() }).len(), 2);
+        assert_eq!(ids(SearchQuery { sort_by: "price".to_owned(), ..SearchQuery::default() }), ["free", "dear", "unlisted"]);
+        assert_eq!(ids(SearchQuery { sort_by: "price".to_owned(), order_by: "descending".to_owned(), ..SearchQuery::default() }), ["dear", "free", "unlisted"]);
+        assert!(ids(SearchQuery { page: Some(isize::MAX), ..SearchQuery::default() }).is_empty());
+    }
+
+    /// A fresh database file in a temporary directory
+    fn database_file(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("crf_2b-store-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("crf_2b.sqlite")
+    }
+
+    fn user_version(path: &Path) -> i64 {
+        Connection::open(path).unwrap().query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn migrations_run_once_and_keep_data() {
+        let path = database_file("migrate");
+        {
+            // a database from before the history table, with a robot in it
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(MIGRATIONS[0]).unwrap();
+            conn.pragma_update(None, "user_version", 1).unwrap();
+            conn.execute(
+                "INSERT INTO robots (id, name, creator_id, creator_name, image, base_cpu, weapon_cpu, cosmetic_cpu,
+                    cluster_count, block_counts, materials_used, robot_extra, item_extra, first_seen, last_seen)
+                VALUES ('a', 'Old', 'c', 'C', '', 1, 2, 3, 4, '{}', '[]', '{}', '{\"views\": 9}', 10, 20)",
+                [],
+            ).unwrap();
+            conn.execute("INSERT INTO prices (robot_id, currency, amount) VALUES ('a', 0, 5)", []).unwrap();
+        }
+        let store = Store::open(&path).unwrap();
+        assert_eq!(user_version(&path), MIGRATIONS.len() as i64);
+        let history = store.history("a", 10).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!((history[0].seen, history[0].views, history[0].prices.clone()), (20, Some(9), vec![Price { currency: 0, amount: 5 }]));
+        drop(store);
+        // opening again has nothing left to do
+        let store = Store::open(&path).unwrap();
+        assert_eq!(store.robot("a").unwrap().unwrap().first_seen, 10);
+        assert_eq!(store.history("a", 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn newer_databases_are_refused() {
+        let path = database_file("too-new");
+        Connection::open(&path).unwrap().pragma_update(None, "user_version", MIGRATIONS.len() as i64 + 1).unwrap();
+        assert!(matches!(Store::open(&path), Err(StoreError::TooNew(version)) if version == MIGRATIONS.len() as i64 + 1));
+    }
+
+    #[test]
+    fn upserts_count_what_changed() {
+        let store = memory();
+        let mut a = test_robot("a", "c", "C");
+        a.prices.push(Price { currency: 1, amount: 10 });
+        a.prices.push(Price { currency: 0, amount: 20 });
+        let counts = store.upsert(&[a.clone(), test_robot("b", "c", "C")], 100, None).unwrap();
+        assert_eq!((counts.added, counts.changed), (2, 0));
+        // price order doesn't count as a change
+        a.prices.reverse();
+        let counts = store.upsert(&[a.clone()], 200, None).unwrap();
+        assert_eq!((counts.added, counts.changed), (0, 0));
+        a.robot.baseCpu = 50;
+        a.prices.pop();
+        let counts = store.upsert(&[a.clone()], 300, None).unwrap();
+        assert_eq!((counts.added, counts.changed), (0, 1));
+        let stored = store.robot("a").unwrap().unwrap();
+        assert_eq!((stored.first_seen, stored.last_seen), (100, 300));
+        assert_eq!(stored.item.robot.baseCpu, 50);
+        assert_eq!(stored.item.prices, [Price { currency: 0, amount: 20 }]);
+    }
+
+    #[test]
+    fn older_snapshots_leave_newer_data_alone() {
+        let store = memory();
+        let mut newer = test_robot("a", "c", "C");
+        newer.robot.name = "Renamed".to_owned();
+        newer.prices.push(Price { currency: 0, amount: 20 });
+        store.upsert(&[newer], 300, None).unwrap();
+        let mut run = store.start_crawl(100).unwrap();
+        let counts = store.upsert(&[test_robot("a", "c", "C")], 200, Some(run.id)).unwrap();
+        assert_eq!((counts.added, counts.changed), (0, 0));
+        let stored = store.robot("a").unwrap().unwrap();
+        assert_eq!(stored.item.robot.name, "Renamed");
+        assert_eq!(stored.item.prices, [Price { currency: 0, amount: 20 }]);
+        assert_eq!(stored.last_seen, 300);
+        assert_eq!(store.history("a", 10).unwrap().len(), 1);
+        // the crawl still counts it as seen
+        store.finish_crawl(&mut run, 400).unwrap();
+        assert_eq!(run.robots_seen, 1);
+    }
+
+    #[test]
+    fn creator_seen_spans_their_robots() {
+        let store = memory();
+        store.upsert(&[test_robot("a", "c", "C")], 100, None).unwrap();
+        store.upsert(&[test_robot("b", "c", "C"), test_robot("x", "other", "O")], 300, None).unwrap();
+        // seeing a robot again doesn't move when it was first seen
+        store.upsert(&[test_robot("a", "c", "C")], 400, None).unwrap();
+        assert_eq!(store.creator_seen("c").unwrap(), Some(CreatorSeen { robots: 2, first_seen: 100, newest_seen: 300 }));
+        assert_eq!(store.creator_seen("OTHER").unwrap(), Some(CreatorSeen { robots: 1, first_seen: 300, newest_seen: 300 }));
+        assert_eq!(store.creator_seen("nobody").unwrap(), None);
+    }
+
+    #[test]
+    fn upserts_record_history_only_when_values_change() {
+        let store = memory();
+        let mut a = test_robot("a", "c", "C");
+        store.upsert(&[a.clone()], 100, None).unwrap();
+        store.upsert(&[a.clone()], 200, None).unwrap();
+        a.robot.name = "Renamed".to_owned();
+        store.upsert(&[a.clone()], 300, None).unwrap();
+        // older responses arriving late don't rewrite history or last_seen
+        store.upsert(&[test_robot("a", "c", "C")], 250, None).unwrap();
+        let history: Vec<(i64, String)> = store.history("a", 10).unwrap().into_iter().map(|point| (point.seen, point.name)).collect();
+        assert_eq!(history, [(100, "Robot a".to_owned()), (300, "Renamed".to_owned())]);
+        assert_eq!(store.robot("a").unwrap().unwrap().last_seen, 300);
+        assert_eq!(store.history("a", 1).unwrap()[0].seen, 300);
+    }
+}
@@ -0,0 +1,143 @@
+//! Names for the block and material IDs in robots.
+//!
+//! The catalogue is a JSON data file (`catalogue.json` in the repository root) so it
+//! can be updated without recompiling; crf_2b loads it at start-up and serves it to
+//! the front-end at `/crf-api/catalogue`.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Catalogue format this build understands
+pub const CATALOGUE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockCategory {
+    Armor,
+    Weapons,
+    Movement,
+    Cosmetics,
+}
+
+impl BlockCategory {
+    /// Display order
+    pub const ALL: [BlockCategory; 4] = [Self::Armor, Self::Weapons, Self::Movement, Self::Cosmetics];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Armor => "Armor",
+            Self::Weapons => "Weapons",
+            Self::Movement => "Movement",
+            Self::Cosmetics => "Cosmetics",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct BlockInfo {
+    pub name: String,
+    pub category: BlockCategory,
+    /// Image path or URL, relative to the site root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct MaterialInfo {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Catalogue {
+    pub version: u32,
+    #[serde(default)]
+    pub blocks: HashMap<usize, BlockInfo>,
+    #[serde(default)]
+    pub materials: HashMap<usize, MaterialInfo>,
+}
+
+impl Default for Catalogue {
+    fn default() -> Self {
+        Self {
+            version: CATALOGUE_VERSION,
+            blocks: HashMap::new(),
+            materials: HashMap::new(),
+        }
+    }
+}
+
+/// Block counts of one category; `category` is `None` for IDs missing from the catalogue
+pub struct BlockGroup<'a> {
+    pub category: Option<BlockCategory>,
+    /// (block ID, catalogue entry, count)
+    pub blocks: Vec<(usize, Option<&'a BlockInfo>, usize)>,
+}
+
+impl BlockGroup<'_> {
+    pub fn name(&self) -> &'static str {
+        self.category.map(|c| c.name()).unwrap_or("Unknown")
+    }
+
+    pub fn total(&self) -> usize {
+        self.blocks.iter().map(|(_, _, count)| count).sum()
+    }
+}
+
+impl Catalogue {
+    /// Block counts split by category, in display order, with unknown blocks last.
+    /// Empty groups are left out.
+    pub fn group_blocks(&self, block_counts: &HashMap<usize, usize>) -> Vec<BlockGroup<'_>> {
+        let mut groups: Vec<BlockGroup> = BlockCategory::ALL.iter()
+            .map(|category| BlockGroup { category: Some(*category), blocks: Vec::new() })
+            .chain(std::iter::once(BlockGroup { category: None, blocks: Vec::new() }))
+            .collect();
+        for (id, count) in block_counts.iter() {
+            let info = self.blocks.get(id);
+            let index = match info {
+                Some(info) => BlockCategory::ALL.iter().position(|c| *c == info.category).unwrap(),
+                None => BlockCategory::ALL.len(),
+            };
+            groups[index].blocks.push((*id, info, *count));
+        }
+        groups.retain(|group| !group.blocks.is_empty());
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The catalogue shipped in the repository
+    fn shipped() -> Catalogue {
+        serde_json::from_str(include_str!("../../catalogue.json")).unwrap()
+    }
+
+    #[test]
+    fn shipped_catalogue_loads() {
+        let catalogue = shipped();
+        assert_eq!(catalogue.version, CATALOGUE_VERSION);
+        assert!(!catalogue.materials.is_empty());
+        for category in BlockCategory::ALL {
+            assert!(catalogue.blocks.values().any(|block| block.category == category), "no {} blocks", category.name());
+        }
+    }
+
+    #[test]
+    fn groups_by_category_with_unknown_last() {
+        let catalogue = shipped();
+        let mut ids: Vec<usize> = catalogue.blocks.keys().copied().collect();
+        ids.sort_unstable();
+        let unknown = ids.last().unwrap() + 1;
+        let counts: HashMap<usize, usize> = ids.iter().map(|id| (*id, 2)).chain([(unknown, 5)]).collect();
+        let groups = catalogue.group_blocks(&counts);
+        let categories: Vec<Option<BlockCategory>> = groups.iter().map(|group| group.category).collect();
+        assert_eq!(categories, [Some(BlockCategory::Armor), Some(BlockCategory::Weapons), Some(BlockCategory::Movement), Some(BlockCategory::Cosmetics), None]);
+        assert_eq!(groups.last().unwrap().name(), "Unknown");
+        assert_eq!(groups.last().unwrap().total(), 5);
+        assert_eq!(groups.iter().map(BlockGroup::total).sum::<usize>(), ids.len() * 2 + 5);
+    }
+}
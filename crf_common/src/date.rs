@@ -0,0 +1,142 @@
+/// Parse an RFC 3339-ish date to unix seconds: `YYYY-MM-DD`, optionally followed by
+/// `THH:MM:SS`, fractional seconds and a `Z` or `+HH:MM` offset. Dates without an offset are UTC.
+pub fn parse_date(date: &str) -> Option<i64> {
+    let (day, time) = match date.split_once(['T', 't']) {
+        Some((day, time)) => (day, Some(time)),
+        None => (date, None),
+    };
+    let (year, month, day) = match day.as_bytes() {
+        [_, _, _, _, b'-', _, _, b'-', _, _] => (number(&day[..4])?, number(&day[5..7])?, number(&day[8..])?),
+        _ => return None,
+    };
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    let seconds = match time {
+        Some(time) => time_seconds(time)?,
+        None => 0,
+    };
+    Some(days_from_civil(year, month, day) * 86400 + seconds)
+}
+
+/// Seconds since midnight UTC for `HH:MM:SS[.fff][Z|±HH:MM]`
+fn time_seconds(time: &str) -> Option<i64> {
+    let (hms, rest) = (time.get(..8)?, &time[8..]);
+    let (hour, minute, second) = match hms.as_bytes() {
+        [_, _, b':', _, _, b':', _, _] => (number(&hms[..2])?, number(&hms[3..5])?, number(&hms[6..])?),
+        _ => return None,
+    };
+    // 60 for leap seconds
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let rest = match rest.strip_prefix('.') {
+        Some(fraction) => {
+            let digits = fraction.find(|c: char| !c.is_ascii_digit()).unwrap_or(fraction.len());
+            if digits == 0 {
+                return None;
+            }
+            &fraction[digits..]
+        },
+        None => rest,
+    };
+    let offset = match rest {
+        "" | "Z" | "z" => 0,
+        _ => {
+            let sign = match rest.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let (hours, minutes) = match rest.as_bytes() {
+                [_, _, _, b':', _, _] => (number(&rest[1..3])?, number(&rest[4..])?),
+                _ => return None,
+            };
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            sign * (hours * 3600 + minutes * 60)
+        },
+    };
+    Some(hour * 3600 + minute * 60 + second - offset)
+}
+
+fn number(digits: &str) -> Option<i64> {
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 (Howard Hinnant's `days_from_civil`)
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("1970-01-02"), Some(86400));
+        assert_eq!(parse_date("2023-03-01T00:00:00Z"), Some(1677628800));
+        assert_eq!(parse_date("1969-12-31T23:59:59Z"), Some(-1));
+    }
+
+    #[test]
+    fn times_and_offsets() {
+        assert_eq!(parse_date("1970-01-01T01:02:03"), Some(3723));
+        assert_eq!(parse_date("1970-01-01T01:02:03.250Z"), Some(3723));
+        assert_eq!(parse_date("1970-01-01T05:00:00+05:00"), Some(0));
+        assert_eq!(parse_date("1969-12-31T19:00:00-05:00"), Some(0));
+        assert_eq!(parse_date("1970-01-01t00:00:00z"), Some(0));
+        assert_eq!(parse_date("1970-01-01T23:59:60Z"), Some(86400));
+    }
+
+    #[test]
+    fn month_lengths() {
+        assert!(parse_date("2023-01-31").is_some());
+        assert!(parse_date("2023-04-31").is_none());
+        assert!(parse_date("2023-02-28").is_some());
+        assert!(parse_date("2023-02-29").is_none());
+        assert!(parse_date("2024-02-29").is_some());
+        assert!(parse_date("1900-02-29").is_none());
+        assert!(parse_date("2000-02-29").is_some());
+    }
+
+    #[test]
+    fn out_of_range_fields() {
+        assert!(parse_date("2023-00-01").is_none());
+        assert!(parse_date("2023-13-01").is_none());
+        assert!(parse_date("2023-01-00").is_none());
+        assert!(parse_date("2023-01-01T24:00:00Z").is_none());
+        assert!(parse_date("2023-01-01T23:60:00Z").is_none());
+        assert!(parse_date("2023-01-01T23:59:61Z").is_none());
+        assert!(parse_date("2023-01-01T00:00:00+24:00").is_none());
+    }
+
+    #[test]
+    fn malformed() {
+        for date in ["", "2023", "2023-1-01", "2023-01-1", "23-01-01", "2023/01/01", "2023-01-01T", "2023-01-01T00:00",
+            "2023-01-01T00:00:00Zjunk", "2023-01-01T00:00:00.", "2023-01-01T00:00:00+0500", "2023-01-01 00:00:00",
+            "+023-01-01", "2023-01-01T+0:00:00", "２０２３-01-01"] {
+            assert_eq!(parse_date(date), None, "{}", date);
+        }
+    }
+}
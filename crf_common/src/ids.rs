@@ -0,0 +1,61 @@
+/// Parse a list of IDs with optional counts, like `345:10, 200`; IDs without a count get 1.
+///
+/// Empty entries are skipped; any other entry that isn't a number makes the whole list invalid.
+/// Counts of 0 parse, so callers can say why they don't make sense.
+pub fn parse_id_counts(list: &str) -> Option<Vec<(usize, usize)>> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once(':') {
+            Some((id, count)) => Some((id.trim().parse().ok()?, count.trim().parse().ok()?)),
+            None => Some((entry.parse().ok()?, 1)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_with_and_without_counts() {
+        assert_eq!(parse_id_counts("345:10,200"), Some(vec![(345, 10), (200, 1)]));
+        assert_eq!(parse_id_counts("0"), Some(vec![(0, 1)]));
+    }
+
+    #[test]
+    fn empty_entries_are_skipped() {
+        assert_eq!(parse_id_counts(""), Some(vec![]));
+        assert_eq!(parse_id_counts(" , ,"), Some(vec![]));
+        assert_eq!(parse_id_counts("1,,2,"), Some(vec![(1, 1), (2, 1)]));
+    }
+
+    #[test]
+    fn whitespace_is_ignored() {
+        assert_eq!(parse_id_counts("  345 : 10 ,\t200 "), Some(vec![(345, 10), (200, 1)]));
+    }
+
+    #[test]
+    fn zeros_parse() {
+        assert_eq!(parse_id_counts("345:0"), Some(vec![(345, 0)]));
+        assert_eq!(parse_id_counts("0:1"), Some(vec![(0, 1)]));
+    }
+
+    #[test]
+    fn malformed_entries_are_invalid() {
+        assert_eq!(parse_id_counts("abc"), None);
+        assert_eq!(parse_id_counts("1,x"), None);
+        assert_eq!(parse_id_counts("345:"), None);
+        assert_eq!(parse_id_counts(":10"), None);
+        assert_eq!(parse_id_counts("1:2:3"), None);
+        assert_eq!(parse_id_counts("-1"), None);
+        assert_eq!(parse_id_counts("1 2"), None);
+    }
+
+    #[test]
+    fn overflow_is_invalid() {
+        assert_eq!(parse_id_counts("18446744073709551616"), None);
+        assert_eq!(parse_id_counts("1:18446744073709551616"), None);
+        assert_eq!(parse_id_counts(&usize::MAX.to_string()), Some(vec![(usize::MAX, 1)]));
+    }
+}
@@ -0,0 +1,12 @@
+//! Search request parsing and the block catalogue, shared by the front-end (crf_tyew)
+//! and the back-end (crf_2b) so both accept and read exactly the same data.
+
+mod catalogue;
+mod date;
+mod ids;
+mod search;
+
+pub use catalogue::{BlockCategory, BlockGroup, BlockInfo, Catalogue, MaterialInfo, CATALOGUE_VERSION};
+pub use date::parse_date;
+pub use ids::parse_id_counts;
+pub use search::{is_valid_guid, FieldError, SearchRequest, MAX_COUNT, MAX_CREATORS, MAX_PAGE, MODE_OPTIONS, ORDER_OPTIONS, SORT_OPTIONS};
@@ -0,0 +1,411 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{parse_date, parse_id_counts};
+
+pub const SORT_OPTIONS: &[&str] = &["default", "cpuPower", "cpuWeapon", "cpuCosmetic", "price", "date", "clusterCount", "views"];
+pub const ORDER_OPTIONS: &[&str] = &["ascending", "descending"];
+pub const MODE_OPTIONS: &[&str] = &["default", "fulltext"];
+/// Most creators one search can be limited to; crf_2b searches upstream once for each
+pub const MAX_CREATORS: usize = 10;
+/// Most results one page can ask for, the same as the CRF's own pages
+pub const MAX_COUNT: usize = 100;
+/// Furthest page a search can ask for; deeper than anyone pages by hand
+pub const MAX_PAGE: usize = 10_000;
+
+/// A search, as sent to crf_2b and kept in the page's query string
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct SearchRequest {
+    #[serde(rename = "text")]
+    pub text: Option<String>,
+    #[serde(rename = "baseCpuMinimum")]
+    pub base_minimum_cpu: Option<isize>,
+    #[serde(rename = "baseCpuMaximum")]
+    pub base_maximum_cpu: Option<isize>,
+    #[serde(rename = "weaponCpuMinimum")]
+    pub weapon_minimum_cpu: Option<isize>,
+    #[serde(rename = "weaponCpuMaximum")]
+    pub weapon_maximum_cpu: Option<isize>,
+    #[serde(rename = "cosmeticCpuMinimum")]
+    pub cosmetic_minimum_cpu: Option<isize>,
+    #[serde(rename = "cosmeticCpuMaximum")]
+    pub cosmetic_maximum_cpu: Option<isize>,
+    #[serde(rename = "clusterMinimum")]
+    pub cluster_minimum: Option<isize>,
+    #[serde(rename = "clusterMaximum")]
+    pub cluster_maximum: Option<isize>,
+    #[serde(rename = "dateMinimum")]
+    pub date_minimum: Option<String>,
+    #[serde(rename = "dateMaximum")]
+    pub date_maximum: Option<String>,
+    #[serde(rename = "creatorId")]
+    pub creator_id: Option<String>, // GUID
+    #[serde(rename = "page")]
+    pub page: Option<isize>,
+    #[serde(rename = "count")]
+    pub count: Option<isize>,
+    #[serde(rename = "sortBy")]
+    pub sort_by: String,
+    #[serde(rename = "orderBy")]
+    pub order_by: String,
+    // crf_2b handles everything below itself, since the CRF can't; see LOCAL_FIELDS
+    #[serde(rename = "priceMinimum")]
+    pub price_minimum: Option<isize>,
+    #[serde(rename = "priceMaximum")]
+    pub price_maximum: Option<isize>,
+    /// Currency code the price range applies to; any currency when unset
+    #[serde(rename = "priceCurrency")]
+    pub price_currency: Option<usize>,
+    /// Block IDs with minimum counts, e.g. `345:10,200`
+    #[serde(rename = "blocksIncluded")]
+    pub blocks_included: Option<String>,
+    /// Block IDs with counts the robot must stay below, e.g. `17,18:5`
+    #[serde(rename = "blocksExcluded")]
+    pub blocks_excluded: Option<String>,
+    #[serde(rename = "materialsIncluded")]
+    pub materials_included: Option<String>,
+    #[serde(rename = "materialsExcluded")]
+    pub materials_excluded: Option<String>,
+    /// Creator GUIDs separated by commas, for robots by any of them (with `creatorId`)
+    #[serde(rename = "creatorIds")]
+    pub creator_ids: Option<String>,
+    /// `fulltext` to search robot and creator names with crf_2b's own index
+    #[serde(rename = "mode")]
+    pub mode: Option<String>,
+}
+
+impl Default for SearchRequest {
+    fn default() -> Self {
+        Self {
+            text: None,
+            base_minimum_cpu: None,
+            base_maximum_cpu: None,
+            weapon_minimum_cpu: None,
+            weapon_maximum_cpu: None,
+            cosmetic_minimum_cpu: None,
+            cosmetic_maximum_cpu: None,
+            cluster_minimum: None,
+            cluster_maximum: None,
+            date_minimum: None,
+            date_maximum: None,
+            creator_id: None,
+            page: None,
+            count: None,
+            sort_by: "default".to_owned(),
+            order_by: "ascending".to_owned(),
+            price_minimum: None,
+            price_maximum: None,
+            price_currency: None,
+            blocks_included: None,
+            blocks_excluded: None,
+            materials_included: None,
+            materials_excluded: None,
+            creator_ids: None,
+            mode: None,
+        }
+    }
+}
+
+/// A problem with one field of a search, named by its JSON key
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &str, message: &str) -> Self {
+        Self {
+            field: field.to_owned(),
+            message: message.to_owned(),
+        }
+    }
+}
+
+impl SearchRequest {
+    /// JSON keys of the fields crf_2b handles itself, which aren't sent to the CRF
+    pub const LOCAL_FIELDS: &'static [&'static str] = &[
+        "priceMinimum", "priceMaximum", "priceCurrency",
+        "blocksIncluded", "blocksExcluded", "materialsIncluded", "materialsExcluded",
+        "creatorIds", "mode",
+    ];
+
+    /// Check the search for values upstream would reject or that can't match anything.
+    /// The front-end checks as you type and crf_2b before forwarding a search.
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        let ranges = [
+            ("baseCpuMinimum", self.base_minimum_cpu, "baseCpuMaximum", self.base_maximum_cpu),
+            ("weaponCpuMinimum", self.weapon_minimum_cpu, "weaponCpuMaximum", self.weapon_maximum_cpu),
+            ("cosmeticCpuMinimum", self.cosmetic_minimum_cpu, "cosmeticCpuMaximum", self.cosmetic_maximum_cpu),
+            ("clusterMinimum", self.cluster_minimum, "clusterMaximum", self.cluster_maximum),
+            ("priceMinimum", self.price_minimum, "priceMaximum", self.price_maximum),
+        ];
+        for (min_field, min, max_field, max) in ranges {
+            if min.map(|x| x < 0).unwrap_or(false) {
+                errors.push(FieldError::new(min_field, "must not be negative"));
+            }
+            if max.map(|x| x < 0).unwrap_or(false) {
+                errors.push(FieldError::new(max_field, "must not be negative"));
+            }
+            if let (Some(min), Some(max)) = (min, max) {
+                if min > max {
+                    errors.push(FieldError::new(max_field, "must not be less than the minimum"));
+                }
+            }
+        }
+        let date_minimum = self.date_minimum.as_deref().map(parse_date);
+        let date_maximum = self.date_maximum.as_deref().map(parse_date);
+        for (field, date) in [("dateMinimum", date_minimum), ("dateMaximum", date_maximum)] {
+            if date == Some(None) {
+                errors.push(FieldError::new(field, "must be a date"));
+            }
+        }
+        if let (Some(Some(min)), Some(Some(max))) = (date_minimum, date_maximum) {
+            if min > max {
+                errors.push(FieldError::new("dateMaximum", "must not be before the minimum"));
+            }
+        }
+        if let Some(creator) = &self.creator_id {
+            if !is_valid_guid(creator) {
+                errors.push(FieldError::new("creatorId", "must be a creator GUID"));
+            }
+        }
+        if let Some(creators) = &self.creator_ids {
+            if !creators.split(',').map(str::trim).filter(|c| !c.is_empty()).all(is_valid_guid) {
+                errors.push(FieldError::new("creatorIds", "must be creator GUIDs separated by commas"));
+            } else if self.creators().len() > MAX_CREATORS {
+                errors.push(FieldError::new("creatorIds", &format!("must not list more than {} creators", MAX_CREATORS)));
+            }
+        }
+        match self.page {
+            Some(page) if page < 1 => errors.push(FieldError::new("page", "must be at least 1")),
+            Some(page) if page as usize > MAX_PAGE => errors.push(FieldError::new("page", &format!("must be at most {}", MAX_PAGE))),
+            _ => {},
+        }
+        match self.count {
+            Some(count) if count < 1 => errors.push(FieldError::new("count", "must be at least 1")),
+            Some(count) if count as usize > MAX_COUNT => errors.push(FieldError::new("count", &format!("must be at most {}", MAX_COUNT))),
+            _ => {},
+        }
+        for (field, list) in [("blocksIncluded", &self.blocks_included), ("blocksExcluded", &self.blocks_excluded)] {
+            if let Some(list) = list {
+                match parse_id_counts(list) {
+                    Some(ids) if ids.iter().any(|(_, count)| *count == 0) => {
+                        errors.push(FieldError::new(field, "block counts must be at least 1"));
+                    },
+                    Some(_) => {},
+                    None => errors.push(FieldError::new(field, "must be block IDs like 345 or 345:10, separated by commas")),
+                }
+            }
+        }
+        for (field, list) in [("materialsIncluded", &self.materials_included), ("materialsExcluded", &self.materials_excluded)] {
+            if let Some(list) = list {
+                match parse_id_counts(list) {
+                    Some(_) if !list.contains(':') => {},
+                    _ => errors.push(FieldError::new(field, "must be material IDs separated by commas")),
+                }
+            }
+        }
+        if let Some(mode) = &self.mode {
+            if !MODE_OPTIONS.contains(&mode.as_str()) {
+                errors.push(FieldError::new("mode", "unknown mode"));
+            }
+        }
+        if self.is_fulltext() && self.text.as_deref().map(|t| t.trim().is_empty()).unwrap_or(true) {
+            errors.push(FieldError::new("text", "is required for full-text search"));
+        }
+        if !SORT_OPTIONS.contains(&self.sort_by.as_str()) {
+            errors.push(FieldError::new("sortBy", "unknown sort"));
+        }
+        if !ORDER_OPTIONS.contains(&self.order_by.as_str()) {
+            errors.push(FieldError::new("orderBy", "unknown order"));
+        }
+        errors
+    }
+
+    pub fn is_fulltext(&self) -> bool {
+        self.mode.as_deref() == Some("fulltext")
+    }
+
+    /// Every creator the search is limited to, without repeats (GUIDs ignore case)
+    pub fn creators(&self) -> Vec<String> {
+        let mut creators: Vec<String> = Vec::new();
+        let ids = self.creator_id.iter()
+            .chain(self.creator_ids.iter())
+            .flat_map(|ids| ids.split(','))
+            .map(str::trim)
+            .filter(|id| !id.is_empty());
+        for id in ids {
+            if !creators.iter().any(|c| c.eq_ignore_ascii_case(id)) {
+                creators.push(id.to_owned());
+            }
+        }
+        creators
+    }
+
+    /// Limit the search to these creators. The CRF takes one; for more, crf_2b searches
+    /// once per creator and merges the results.
+    pub fn set_creators(&mut self, ids: &[String]) {
+        self.creator_id = None;
+        self.creator_ids = None;
+        match ids {
+            [] => {},
+            [id] => self.creator_id = Some(id.clone()),
+            ids => self.creator_ids = Some(ids.join(",")),
+        }
+    }
+}
+
+/// `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx` with hex digits
+pub fn is_valid_guid(guid: &str) -> bool {
+    let groups: Vec<&str> = guid.split('-').collect();
+    groups.len() == 5
+        && groups.iter().zip([8, 4, 4, 4, 12]).all(|(group, len)| group.len() == len && group.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUID: &str = "0123abcd-4567-89ef-ABCD-0123456789ab";
+
+    fn fields(search: &SearchRequest) -> Vec<String> {
+        search.validate().into_iter().map(|e| e.field).collect()
+    }
+
+    #[test]
+    fn default_is_valid() {
+        assert!(SearchRequest::default().validate().is_empty());
+    }
+
+    #[test]
+    fn range_boundaries() {
+        let search = SearchRequest { base_minimum_cpu: Some(0), base_maximum_cpu: Some(0), ..Default::default() };
+        assert!(fields(&search).is_empty());
+        let search = SearchRequest { base_minimum_cpu: Some(-1), ..Default::default() };
+        assert_eq!(fields(&search), ["baseCpuMinimum"]);
+        let search = SearchRequest { price_maximum: Some(-1), ..Default::default() };
+        assert_eq!(fields(&search), ["priceMaximum"]);
+        let search = SearchRequest { cluster_minimum: Some(5), cluster_maximum: Some(4), ..Default::default() };
+        assert_eq!(fields(&search), ["clusterMaximum"]);
+        let search = SearchRequest { cluster_minimum: Some(5), cluster_maximum: Some(5), ..Default::default() };
+        assert!(fields(&search).is_empty());
+    }
+
+    #[test]
+    fn page_and_count() {
+        let search = SearchRequest { page: Some(1), count: Some(1), ..Default::default() };
+        assert!(fields(&search).is_empty());
+        let search = SearchRequest { page: Some(0), count: Some(0), ..Default::default() };
+        assert_eq!(fields(&search), ["page", "count"]);
+        let search = SearchRequest { page: Some(MAX_PAGE as isize), count: Some(MAX_COUNT as isize), ..Default::default() };
+        assert!(fields(&search).is_empty());
+        let search = SearchRequest { page: Some(isize::MAX), count: Some(MAX_COUNT as isize + 1), ..Default::default() };
+        assert_eq!(fields(&search), ["page", "count"]);
+        assert_eq!(search.validate()[1].message, "must be at most 100");
+    }
+
+    #[test]
+    fn invalid_dates() {
+        let search = SearchRequest {
+            date_minimum: Some("2023-02-29".to_owned()),
+            date_maximum: Some("not a date".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(fields(&search), ["dateMinimum", "dateMaximum"]);
+    }
+
+    #[test]
+    fn dates_are_ordered_by_time_not_text() {
+        let dates = |min: &str, max: &str| SearchRequest {
+            date_minimum: Some(min.to_owned()),
+            date_maximum: Some(max.to_owned()),
+            ..Default::default()
+        };
+        // 05:00Z is before 06:00Z, though "10" sorts after "06"
+        assert!(fields(&dates("2023-01-01T10:00:00+05:00", "2023-01-01T06:00:00Z")).is_empty());
+        assert_eq!(fields(&dates("2023-01-01T06:00:00Z", "2023-01-01T10:00:00+05:00")), ["dateMaximum"]);
+        assert!(fields(&dates("2023-01-01", "2023-01-01T00:00:00Z")).is_empty());
+        assert!(fields(&dates("2023-01-01T00:00:00Z", "2023-01-01")).is_empty());
+        assert_eq!(fields(&dates("2023-01-01T00:00:01Z", "2023-01-01")), ["dateMaximum"]);
+    }
+
+    #[test]
+    fn creators() {
+        let search = SearchRequest { creator_id: Some(GUID.to_owned()), ..Default::default() };
+        assert!(fields(&search).is_empty());
+        let search = SearchRequest { creator_id: Some(GUID[1..].to_owned()), ..Default::default() };
+        assert_eq!(fields(&search), ["creatorId"]);
+        let search = SearchRequest { creator_ids: Some(format!("{}, {},", GUID, GUID)), ..Default::default() };
+        assert!(fields(&search).is_empty());
+        let search = SearchRequest { creator_ids: Some(format!("{},nope", GUID)), ..Default::default() };
+        assert_eq!(fields(&search), ["creatorIds"]);
+        let many = |n: usize| (0..n).map(|i| format!("{:08x}-4567-89ef-abcd-0123456789ab", i)).collect::<Vec<_>>().join(",");
+        let search = SearchRequest { creator_ids: Some(many(MAX_CREATORS)), ..Default::default() };
+        assert!(fields(&search).is_empty());
+        let search = SearchRequest { creator_ids: Some(many(MAX_CREATORS + 1)), ..Default::default() };
+        assert_eq!(fields(&search), ["creatorIds"]);
+    }
+
+    #[test]
+    fn guids() {
+        assert!(is_valid_guid(GUID));
+        assert!(!is_valid_guid(""));
+        assert!(!is_valid_guid("0123abcd-4567-89ef-ABCD-0123456789a"));
+        assert!(!is_valid_guid("0123abcd-4567-89ef-ABCD-0123456789abc"));
+        assert!(!is_valid_guid("0123abcd-4567-89ef-ABCD0123456789ab"));
+        assert!(!is_valid_guid("0123abcg-4567-89ef-ABCD-0123456789ab"));
+        assert!(!is_valid_guid("{0123abcd-4567-89ef-ABCD-0123456789ab}"));
+    }
+
+    #[test]
+    fn block_and_material_lists() {
+        let search = SearchRequest { blocks_included: Some("345:10, 200".to_owned()), materials_excluded: Some("1,2".to_owned()), ..Default::default() };
+        assert!(fields(&search).is_empty());
+        let search = SearchRequest { blocks_excluded: Some("345:0".to_owned()), ..Default::default() };
+        assert_eq!(search.validate()[0].message, "block counts must be at least 1");
+        let search = SearchRequest { blocks_included: Some("x".to_owned()), materials_included: Some("1:2".to_owned()), ..Default::default() };
+        assert_eq!(fields(&search), ["blocksIncluded", "materialsIncluded"]);
+    }
+
+    #[test]
+    fn modes_and_sorts() {
+        let search = SearchRequest { mode: Some("fulltext".to_owned()), text: Some("  ".to_owned()), ..Default::default() };
+        assert_eq!(fields(&search), ["text"]);
+        let search = SearchRequest { mode: Some("fulltext".to_owned()), text: Some("tank".to_owned()), ..Default::default() };
+        assert!(fields(&search).is_empty());
+        let search = SearchRequest { mode: Some("regex".to_owned()), sort_by: "name".to_owned(), order_by: "up".to_owned(), ..Default::default() };
+        assert_eq!(fields(&search), ["mode", "sortBy", "orderBy"]);
+    }
+
+    #[test]
+    fn set_creators_round_trips() {
+        let mut search = SearchRequest::default();
+        let ids = vec![GUID.to_owned(), "other".to_owned()];
+        search.set_creators(&ids);
+        assert_eq!(search.creator_id, None);
+        assert_eq!(search.creators(), ids);
+        search.set_creators(&ids[..1]);
+        assert_eq!(search.creator_id.as_deref(), Some(GUID));
+        assert_eq!(search.creators(), &ids[..1]);
+        search.set_creators(&[]);
+        assert!(search.creators().is_empty());
+    }
+
+    #[test]
+    fn creators_are_not_repeated() {
+        let search = SearchRequest {
+            creator_id: Some(GUID.to_owned()),
+            creator_ids: Some(format!("{}, {}", GUID.to_lowercase(), GUID.to_uppercase())),
+            ..Default::default()
+        };
+        assert_eq!(search.creators(), [GUID]);
+    }
+
+    #[test]
+    fn missing_fields_default() {
+        let search: SearchRequest = serde_json::from_str(r#"{"text": "tank"}"#).unwrap();
+        assert_eq!(search, SearchRequest { text: Some("tank".to_owned()), ..Default::default() });
+    }
+}
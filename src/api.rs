@@ -1,23 +1,170 @@
 use serde::{Deserialize, Serialize};
-use gloo_net::http::Request;
+use gloo_net::http::{Request, Response};
+use crf_common::{Catalogue, CATALOGUE_VERSION};
 
-#[derive(Clone, Deserialize)]
+
+pub use crf_common::{is_valid_guid, FieldError, SearchRequest, MAX_CREATORS};
+
+#[derive(Clone, Deserialize, Default)]
 pub struct SearchResults {
     pub results: Vec<ResultItem>,
+    /// Set when crf_2b filtered the results itself
+    #[serde(rename = "postFilter", default)]
+    pub post_filter: Option<PostFilterInfo>,
+    #[serde(default)]
+    pub source: ResultSource,
+    /// For archived results, when crf_2b's store was last updated (unix seconds)
+    #[serde(rename = "archivedAt", default)]
+    pub archived_at: Option<i64>,
+    /// Set for full-text searches
+    #[serde(rename = "fullText", default)]
+    pub full_text: Option<FullTextInfo>,
+}
+
+/// Where crf_2b got results from
+#[derive(Clone, Copy, Deserialize, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultSource {
+    /// The CRF
+    Upstream,
+    /// crf_2b's archive, because the CRF is down
+    Cache,
+    /// crf_2b's full-text search of the robots it has seen
+    Index,
+}
+
+impl Default for ResultSource {
+    fn default() -> Self {
+        Self::Upstream
+    }
+}
+
+/// How much of the CRF crf_2b looked through for a post-filtered search
+#[derive(Clone, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PostFilterInfo {
+    pub scanned_pages: usize,
+    pub scanned_robots: usize,
+    /// False when crf_2b gave up before finding enough matches
+    pub complete: bool,
+}
+
+/// How many robots a full-text search matched
+#[derive(Clone, Deserialize, PartialEq, Debug)]
+pub struct FullTextInfo {
+    /// Best matches that were filtered and paged
+    pub hits: usize,
+    /// False when there were more matches than that
+    pub complete: bool,
 }
 
 #[derive(Clone, Deserialize, PartialEq)]
 pub struct ResultItem {
     pub robot: Robot,
     pub prices: Vec<Price>,
+    /// Where a full-text search matched
+    #[serde(default)]
+    pub highlights: Option<Highlights>,
+}
+
+/// Byte ranges of matched words, for full-text searches
+#[derive(Clone, Deserialize, PartialEq, Default)]
+pub struct Highlights {
+    pub name: Vec<(usize, usize)>,
+    #[serde(rename = "creatorName")]
+    pub creator_name: Vec<(usize, usize)>,
 }
 
 #[derive(Clone, Deserialize, PartialEq)]
 pub struct Price {
-    pub currency: usize,
+    pub currency: Currency,
     pub amount: usize,
 }
 
+/// A robot's tracked values at one time, from crf_2b's store
+#[derive(Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryPoint {
+    /// Unix seconds
+    pub seen: i64,
+    pub name: String,
+    pub base_cpu: usize,
+    pub weapon_cpu: usize,
+    pub cosmetic_cpu: usize,
+    pub cluster_count: usize,
+    pub views: Option<u64>,
+    pub prices: Vec<Price>,
+}
+
+/// A creator crf_2b has seen, suggested for what was typed
+#[derive(Clone, Deserialize, PartialEq, Debug)]
+pub struct CreatorSuggestion {
+    pub id: String,
+    pub name: String,
+    /// Robots by them that crf_2b has seen
+    pub robots: usize,
+}
+
+/// What a price is paid in, from the CRF's numeric currency code
+#[derive(Clone, Copy, Deserialize, PartialEq, Eq, Hash, Debug)]
+#[serde(from = "usize")]
+pub enum Currency {
+    Techpoints,
+    Bloxcoins,
+    /// A code we don't know about yet
+    Unknown(usize),
+}
+
+impl From<usize> for Currency {
+    fn from(code: usize) -> Self {
+        match code {
+            0 => Self::Techpoints,
+            1 => Self::Bloxcoins,
+            code => Self::Unknown(code),
+        }
+    }
+}
+
+impl Currency {
+    pub fn code(&self) -> usize {
+        match self {
+            Self::Techpoints => 0,
+            Self::Bloxcoins => 1,
+            Self::Unknown(code) => *code,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Techpoints => "Techpoints".to_owned(),
+            Self::Bloxcoins => "Bloxcoins".to_owned(),
+            Self::Unknown(code) => format!("Unknown currency {}", code),
+        }
+    }
+}
+
+impl Price {
+    /// Prices in the same order on every robot, by currency code, whatever order the CRF sent
+    pub fn by_currency(prices: &[Price]) -> Vec<Price> {
+        let mut prices = prices.to_vec();
+        prices.sort_by_key(|price| price.currency.code());
+        prices
+    }
+
+    /// Amount with thousands separators, e.g. `12,345`
+    pub fn formatted_amount(&self) -> String {
+        let digits = self.amount.to_string();
+        let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+        for (i, c) in digits.chars().enumerate() {
+            if i != 0 && (digits.len() - i) % 3 == 0 {
+                out.push(',');
+            }
+            out.push(c);
+        }
+        out
+    }
+}
+
 #[allow(non_snake_case)]
 #[derive(Clone, Deserialize, PartialEq)]
 pub struct Robot {
@@ -35,70 +182,185 @@ pub struct Robot {
     // TODO(MAYBE) min/max offsets
 }
 
-#[derive(Serialize, Clone)]
-pub struct SearchRequest {
-    #[serde(rename = "text")]
-    pub text: Option<String>,
-    #[serde(rename = "baseCpuMinimum")]
-    pub base_minimum_cpu: Option<isize>,
-    #[serde(rename = "baseCpuMaximum")]
-    pub base_maximum_cpu: Option<isize>,
-    #[serde(rename = "weaponCpuMinimum")]
-    pub weapon_minimum_cpu: Option<isize>,
-    #[serde(rename = "weaponCpuMaximum")]
-    pub weapon_maximum_cpu: Option<isize>,
-    #[serde(rename = "cosmeticCpuMinimum")]
-    pub cosmetic_minimum_cpu: Option<isize>,
-    #[serde(rename = "cosmeticCpuMaximum")]
-    pub cosmetic_maximum_cpu: Option<isize>,
-    #[serde(rename = "clusterMinimum")]
-    pub cluster_minimum: Option<isize>,
-    #[serde(rename = "clusterMaximum")]
-    pub cluster_maximum: Option<isize>,
-    #[serde(rename = "dateMinimum")]
-    pub date_minimum: Option<String>,
-    #[serde(rename = "dateMaximum")]
-    pub date_maximum: Option<String>,
-    #[serde(rename = "creatorId")]
-    pub creator_id: Option<String>, // GUID
-    #[serde(rename = "page")]
-    pub page: Option<isize>,
-    #[serde(rename = "count")]
-    pub count: Option<isize>,
-    #[serde(rename = "sortBy")]
-    pub sort_by: String,
-    #[serde(rename = "orderBy")]
-    pub order_by: String,
-}
-
-impl Default for SearchRequest {
-    fn default() -> Self {
-        Self {
-            text: None,
-            base_minimum_cpu: None,
-            base_maximum_cpu: None,
-            weapon_minimum_cpu: None,
-            weapon_maximum_cpu: None,
-            cosmetic_minimum_cpu: None,
-            cosmetic_maximum_cpu: None,
-            cluster_minimum: None,
-            cluster_maximum: None,
-            date_minimum: None,
-            date_maximum: None,
-            creator_id: None,
-            page: None,
-            count: None,
-            sort_by: "default".to_owned(),
-            order_by: "ascending".to_owned(),
+/// Convert the `YYYY-MM-DD` value of an `<input type="date">` to the RFC 3339
+/// timestamp that upstream's `dateMinimum`/`dateMaximum` expect.
+/// The maximum includes the whole day.
+pub fn date_input_to_upstream(date: &str, end_of_day: bool) -> Option<String> {
+    let date = date.trim();
+    if date.is_empty() {
+        None
+    } else if end_of_day {
+        Some(format!("{}T23:59:59Z", date))
+    } else {
+        Some(format!("{}T00:00:00Z", date))
+    }
+}
+
+/// The `YYYY-MM-DD` value for an `<input type="date">` showing an upstream timestamp
+pub fn upstream_to_date_input(date: &str) -> String {
+    date.get(..10).unwrap_or(date).to_owned()
+}
+
+/// Error body returned by every /crf-api endpoint
+#[derive(Clone, Deserialize, PartialEq, Debug)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    pub upstream_status: Option<u16>,
+    pub request_id: Option<String>,
+    #[serde(default)]
+    pub fields: Vec<FieldError>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ApiError {
+    /// The request never got a response
+    Network(String),
+    /// The back-end answered with one of its errors
+    Server { status: u16, body: ErrorBody },
+    /// Something else answered with an error (e.g. a proxy in front of the back-end)
+    Http { status: u16, text: String },
+    /// The response wasn't what we expected
+    Decode(String),
+}
+
+impl ApiError {
+    async fn from_response(response: Response) -> Self {
+        let status = response.status();
+        let text = match response.text().await {
+            Ok(text) => text,
+            Err(e) => return Self::Decode(e.to_string()),
+        };
+        match serde_json::from_str::<ErrorBody>(&text) {
+            Ok(body) => Self::Server { status, body },
+            Err(_) => Self::Http { status, text },
+        }
+    }
+
+    /// Short explanation for users
+    pub fn summary(&self) -> String {
+        match self {
+            Self::Network(_) => "Can't reach the server, check your connection".to_owned(),
+            Self::Server { body, .. } => match body.code.as_str() {
+                "invalid_query" => format!("Invalid search: {}", body.message),
+                "upstream_rejected" => format!("The CRF rejected the search: {}", body.message),
+                "unavailable" | "upstream_unreachable" | "upstream_error" => "The CRF is down, try again later".to_owned(),
+                _ => body.message.clone(),
+            },
+            Self::Http { status, .. } if *status >= 500 => "The server is having problems, try again later".to_owned(),
+            Self::Http { status, .. } => format!("Search failed (HTTP {})", status),
+            Self::Decode(_) => "Got an unexpected response from the server".to_owned(),
+        }
+    }
+
+    /// Everything we know, for bug reports
+    pub fn details(&self) -> String {
+        match self {
+            Self::Network(e) => format!("Network error: {}", e),
+            Self::Server { status, body } => format!(
+                "HTTP {} {}: {} (upstream status: {}, request id: {}){}",
+                status,
+                body.code,
+                body.message,
+                body.upstream_status.map(|s| s.to_string()).unwrap_or_else(|| "none".to_owned()),
+                body.request_id.as_deref().unwrap_or("none"),
+                body.fields.iter().map(|f| format!("\n{}: {}", f.field, f.message)).collect::<String>(),
+            ),
+            Self::Http { status, text } => format!("HTTP {}: {}", status, text),
+            Self::Decode(e) => format!("Decode error: {}", e),
+        }
+    }
+
+    /// Whether trying the same request again could work
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Network(_) | Self::Decode(_) => true,
+            Self::Server { body, .. } => !matches!(body.code.as_str(), "invalid_query" | "upstream_rejected" | "not_found"),
+            Self::Http { status, .. } => *status >= 500,
         }
     }
 }
 
-pub async fn search_query(query: &SearchRequest) -> Result<SearchResults, String> {
+pub async fn search_query(query: &SearchRequest) -> Result<SearchResults, ApiError> {
     let response = Request::post("/crf-api/search")
-        .json(query).map_err(|e| e.to_string())?
+        .json(query).map_err(|e| ApiError::Decode(e.to_string()))?
+        .send()
+        .await.map_err(|e| ApiError::Network(e.to_string()))?;
+    if !response.ok() {
+        return Err(ApiError::from_response(response).await);
+    }
+    response.json()
+        .await.map_err(|e| ApiError::Decode(e.to_string()))
+}
+
+pub async fn robot_query(id: &str) -> Result<ResultItem, ApiError> {
+    let response = Request::get(&format!("/crf-api/robot/{}", String::from(js_sys::encode_uri_component(id))))
+        .send()
+        .await.map_err(|e| ApiError::Network(e.to_string()))?;
+    if !response.ok() {
+        return Err(ApiError::from_response(response).await);
+    }
+    response.json()
+        .await.map_err(|e| ApiError::Decode(e.to_string()))
+}
+
+/// How the robot changed over time, oldest first
+pub async fn robot_history_query(id: &str) -> Result<Vec<HistoryPoint>, ApiError> {
+    let response = Request::get(&format!("/crf-api/robot/{}/history", String::from(js_sys::encode_uri_component(id))))
+        .send()
+        .await.map_err(|e| ApiError::Network(e.to_string()))?;
+    if !response.ok() {
+        return Err(ApiError::from_response(response).await);
+    }
+    response.json()
+        .await.map_err(|e| ApiError::Decode(e.to_string()))
+}
+
+/// When crf_2b first saw a creator's robots; the CRF doesn't say when they were uploaded
+#[derive(Clone, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatorSeen {
+    /// Unix seconds
+    pub first_seen: i64,
+    /// When the newest robot was first seen (unix seconds)
+    pub newest_seen: i64,
+}
+
+pub async fn creator_query(id: &str) -> Result<CreatorSeen, ApiError> {
+    let response = Request::get(&format!("/crf-api/creator/{}", String::from(js_sys::encode_uri_component(id))))
         .send()
-        .await.map_err(|e| e.to_string())?;
-    Ok(response.json()
-        .await.map_err(|e| e.to_string())?)
+        .await.map_err(|e| ApiError::Network(e.to_string()))?;
+    if !response.ok() {
+        return Err(ApiError::from_response(response).await);
+    }
+    response.json()
+        .await.map_err(|e| ApiError::Decode(e.to_string()))
+}
+
+/// Creators whose name (or GUID) starts with `prefix`, best first
+pub async fn creators_query(prefix: &str) -> Result<Vec<CreatorSuggestion>, ApiError> {
+    let url = format!("/crf-api/creators?prefix={}", String::from(js_sys::encode_uri_component(prefix)));
+    let response = Request::get(&url)
+        .send()
+        .await.map_err(|e| ApiError::Network(e.to_string()))?;
+    if !response.ok() {
+        return Err(ApiError::from_response(response).await);
+    }
+    response.json()
+        .await.map_err(|e| ApiError::Decode(e.to_string()))
+}
+
+pub async fn catalogue_query() -> Result<Catalogue, ApiError> {
+    let response = Request::get("/crf-api/catalogue")
+        .send()
+        .await.map_err(|e| ApiError::Network(e.to_string()))?;
+    if !response.ok() {
+        return Err(ApiError::from_response(response).await);
+    }
+    let catalogue: Catalogue = response.json()
+        .await.map_err(|e| ApiError::Decode(e.to_string()))?;
+    if catalogue.version != CATALOGUE_VERSION {
+        return Err(ApiError::Decode(format!("catalogue version {} is not supported (expected {})", catalogue.version, CATALOGUE_VERSION)));
+    }
+    Ok(catalogue)
 }
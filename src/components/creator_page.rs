@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crf_common::Catalogue;
+use gloo_console as console;
+use wasm_bindgen::JsValue;
+use yew::{html, Component, Context, Html, Properties};
+use yew_icons::{Icon, IconId};
+use yew_router::prelude::*;
+
+use crate::api::{ApiError, CreatorSeen, ResultItem, SearchRequest, SearchResults, creator_query, search_query};
+use crate::route::Route;
+use super::{ErrorBannerComponent, RobotComponent};
+
+/// Robots asked for per page while collecting a creator's robots
+const PAGE_SIZE: isize = 100;
+/// Pages fetched before giving up on collecting everything
+const MAX_PAGES: isize = 20;
+/// Blocks listed under "Most used blocks"
+const TOP_BLOCKS: usize = 10;
+
+pub enum CreatorPageMessage {
+    Loaded(String, isize, Result<SearchResults, ApiError>),
+    /// When this site first saw the creator's robots; an error just means it can't say
+    Seen(String, Result<CreatorSeen, ApiError>),
+    ClickErrorX,
+    ClickRetry,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct CreatorPageProperties {
+    pub id: String,
+    /// Search to go back to
+    pub back: Option<SearchRequest>,
+    pub catalogue: Rc<Catalogue>,
+}
+
+/// Every robot by one creator, oldest upload first, with some totals
+pub struct CreatorPageComponent {
+    /// Creator the robots are for
+    id: String,
+    robots: Vec<ResultItem>,
+    /// Page being fetched, while there may be more
+    next_page: Option<isize>,
+    /// Every robot has been fetched
+    complete: bool,
+    seen: Option<CreatorSeen>,
+    error: Option<ApiError>,
+}
+
+impl CreatorPageComponent {
+    fn request(id: &str, page: isize) -> SearchRequest {
+        SearchRequest {
+            creator_id: Some(id.to_owned()),
+            page: Some(page),
+            count: Some(PAGE_SIZE),
+            sort_by: "date".to_owned(),
+            order_by: "ascending".to_owned(),
+            ..SearchRequest::default()
+        }
+    }
+
+    fn fetch(&mut self, ctx: &Context<Self>, page: isize) {
+        self.next_page = Some(page);
+        self.error = None;
+        let id = ctx.props().id.clone();
+        let callback = ctx.link().callback(|(id, page, result)| CreatorPageMessage::Loaded(id, page, result));
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = search_query(&Self::request(&id, page)).await;
+            callback.emit((id, page, result));
+        });
+    }
+
+    fn restart(&mut self, ctx: &Context<Self>) {
+        self.id = ctx.props().id.clone();
+        self.robots.clear();
+        self.complete = false;
+        self.seen = None;
+        self.fetch(ctx, 1);
+        let id = self.id.clone();
+        let callback = ctx.link().callback(|(id, result)| CreatorPageMessage::Seen(id, result));
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = creator_query(&id).await;
+            callback.emit((id, result));
+        });
+    }
+
+    fn date(secs: i64) -> String {
+        let date = js_sys::Date::new(&JsValue::from_f64(secs as f64 * 1000.0));
+        String::from(date.to_date_string())
+    }
+
+    /// Dates from this site's store, since the CRF has none
+    fn seen_rows(&self) -> Html {
+        match &self.seen {
+            Some(seen) => html! {
+                <>
+                    <tr><th>{"First seen by this site"}</th><td>{Self::date(seen.first_seen)}</td></tr>
+                    <tr><th>{"Newest robot first seen by this site"}</th><td>{Self::date(seen.newest_seen)}</td></tr>
+                </>
+            },
+            None => html! {
+                <tr><th>{"Upload dates"}</th><td>{"Unavailable, the CRF doesn't publish them"}</td></tr>
+            },
+        }
+    }
+
+    fn back_link(ctx: &Context<Self>) -> Html {
+        html! {
+            <div class="robot-page-back">
+                <Link<Route, SearchRequest> to={Route::Search} query={ctx.props().back.clone()}>
+                    {"< Back to results"}
+                </Link<Route, SearchRequest>>
+            </div>
+        }
+    }
+
+    fn robot_link(item: &ResultItem) -> Html {
+        html! {
+            <Link<Route> to={Route::Robot { id: item.robot.id.clone() }}>{&item.robot.name}</Link<Route>>
+        }
+    }
+
+    fn average(&self, cpu: fn(&ResultItem) -> usize) -> String {
+        let total: usize = self.robots.iter().map(cpu).sum();
+        format!("{:.0}", total as f64 / self.robots.len() as f64)
+    }
+
+    fn top_blocks(&self, ctx: &Context<Self>) -> Html {
+        let mut totals: HashMap<usize, usize> = HashMap::new();
+        for item in self.robots.iter() {
+            for (id, count) in item.robot.blockCounts.iter() {
+                *totals.entry(*id).or_insert(0) += count;
+            }
+        }
+        let mut totals: Vec<(usize, usize)> = totals.into_iter().collect();
+        totals.sort_by_key(|(id, count)| (std::cmp::Reverse(*count), *id));
+        html! {
+            <ol class="creator-page-blocks">
+                {
+                    totals.iter().take(TOP_BLOCKS).map(|(id, count)| {
+                        let name = match ctx.props().catalogue.blocks.get(id) {
+                            Some(info) => html!{ <span title={format!("Block {}", id)}>{&info.name}</span> },
+                            None if ctx.props().catalogue.blocks.is_empty() => html!{ <span>{format!("Block {}", id)}</span> },
+                            None => html!{ <span class="catalogue-unknown" title="Not in the block catalogue">{format!("Unknown block {}", id)}</span> },
+                        };
+                        html!{ <li key={id.to_string()}>{name}{format!(" x{}", count)}</li> }
+                    }).collect::<Html>()
+                }
+            </ol>
+        }
+    }
+
+    fn stats(&self, ctx: &Context<Self>) -> Html {
+        let (first, latest) = match (self.robots.first(), self.robots.last()) {
+            (Some(first), Some(latest)) => (first, latest),
+            _ => return html!{},
+        };
+        let count = if !self.complete {
+            format!("{} so far", self.robots.len())
+        } else {
+            self.robots.len().to_string()
+        };
+        html! {
+            <div class="robot-page-info">
+                <table class="robot-page-metadata">
+                    <tr><th>{"Creator ID"}</th><td>{&ctx.props().id}</td></tr>
+                    <tr><th>{"Robots"}</th><td>{count}</td></tr>
+                    <tr><th>{"Average base CPU"}</th><td>{self.average(|item| item.robot.baseCpu)}</td></tr>
+                    <tr><th>{"Average weapon CPU"}</th><td>{self.average(|item| item.robot.weaponCpu)}</td></tr>
+                    <tr><th>{"Average cosmetic CPU"}</th><td>{self.average(|item| item.robot.cosmeticCpu)}</td></tr>
+                    // the CRF sorts by upload date but doesn't tell us the dates
+                    <tr><th>{"First upload"}</th><td>{Self::robot_link(first)}</td></tr>
+                    <tr><th>{"Latest upload"}</th><td>{Self::robot_link(latest)}</td></tr>
+                    {self.seen_rows()}
+                </table>
+                <div class="robot-page-section">
+                    <div class="robot-page-section-header">{"Most used blocks"}</div>
+                    {self.top_blocks(ctx)}
+                </div>
+            </div>
+        }
+    }
+}
+
+impl Component for CreatorPageComponent {
+    type Message = CreatorPageMessage;
+    type Properties = CreatorPageProperties;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let mut page = Self {
+            id: String::new(),
+            robots: Vec::new(),
+            next_page: None,
+            complete: false,
+            seen: None,
+            error: None,
+        };
+        page.restart(ctx);
+        page
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            CreatorPageMessage::Loaded(id, page, result) => {
+                // ignore answers for a creator we've navigated away from
+                if id != ctx.props().id || self.next_page != Some(page) {
+                    return false;
+                }
+                match result {
+                    Ok(results) => {
+                        let full = results.results.len() as isize >= PAGE_SIZE;
+                        // crf_2b gave up scanning, so a short page isn't the last one
+                        let cut_short = results.post_filter.as_ref().map(|info| !info.complete).unwrap_or(false);
+                        self.robots.extend(results.results);
+                        if full && !cut_short && page < MAX_PAGES {
+                            self.fetch(ctx, page + 1);
+                        } else {
+                            self.next_page = None;
+                            self.complete = !full && !cut_short;
+                        }
+                    },
+                    Err(e) => self.error = Some(e),
+                }
+            },
+            CreatorPageMessage::Seen(id, result) => {
+                if id != ctx.props().id {
+                    return false;
+                }
+                if let Err(e) = &result {
+                    console::log!("No first seen dates for creator:", e.details());
+                }
+                self.seen = result.ok();
+            },
+            // stop at what we have
+            CreatorPageMessage::ClickErrorX => {
+                self.error = None;
+                self.next_page = None;
+            },
+            CreatorPageMessage::ClickRetry => match self.next_page {
+                Some(page) => self.fetch(ctx, page),
+                None => self.restart(ctx),
+            },
+        }
+        true
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        if self.id != ctx.props().id {
+            self.restart(ctx);
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let name = self.robots.first().map(|item| item.robot.creatorName.clone()).unwrap_or_else(|| ctx.props().id.clone());
+        html! {
+            <div class="robot-page">
+                {Self::back_link(ctx)}
+                <div class="bot-creator creator-page-name">
+                    <span class="bot-creator-icon"><Icon icon_id={IconId::BootstrapBrush} title={"Creator"} height={"1.1rem".to_owned()}/></span>
+                    <span class="bot-creator-name">{name}</span>
+                </div>
+                {
+                    if let Some(e) = &self.error {
+                        html!{
+                            <ErrorBannerComponent
+                                error={e.clone()}
+                                on_dismiss={ctx.link().callback(|_| CreatorPageMessage::ClickErrorX)}
+                                on_retry={ctx.link().callback(|_| CreatorPageMessage::ClickRetry)}/>
+                        }
+                    } else {
+                        html!{}
+                    }
+                }
+                {self.stats(ctx)}
+                <div class="bot-wrapper">{
+                    if self.robots.is_empty() && self.next_page.is_some() {
+                        html!{ <div class="bot-empty">{"Loading..."}</div> }
+                    } else if self.robots.is_empty() && self.error.is_none() {
+                        html!{ <div class="bot-empty">{"No robots by this creator"}</div> }
+                    } else {
+                        self.robots.iter().map(|bot| {
+                            html!{ <RobotComponent robot={bot.clone()} catalogue={ctx.props().catalogue.clone()} key={bot.robot.id.clone()}/> }
+                        }).collect::<Html>()
+                    }
+                }</div>
+                {
+                    if !self.robots.is_empty() && self.next_page.is_some() && self.error.is_none() {
+                        html!{ <div class="bot-empty">{"Loading more..."}</div> }
+                    } else if !self.complete && self.next_page.is_none() && self.error.is_none() && !self.robots.is_empty() {
+                        html!{ <div class="bot-empty">{format!("Stopped after {} robots", self.robots.len())}</div> }
+                    } else {
+                        html!{}
+                    }
+                }
+            </div>
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use yew::{html, Callback, Component, Context, Html, Properties};
+
+use crate::api::ApiError;
+
+pub enum ErrorBannerMessage {
+    ClickX,
+    ClickRetry,
+    ToggleDetails,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ErrorBannerProperties {
+    pub error: ApiError,
+    pub on_dismiss: Callback<()>,
+    pub on_retry: Callback<()>,
+}
+
+pub struct ErrorBannerComponent {
+    /// Error the details were opened on
+    error: ApiError,
+    show_details: bool,
+}
+
+impl Component for ErrorBannerComponent {
+    type Message = ErrorBannerMessage;
+    type Properties = ErrorBannerProperties;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        Self {
+            error: ctx.props().error.clone(),
+            show_details: false,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            ErrorBannerMessage::ClickX => {
+                ctx.props().on_dismiss.emit(());
+                false
+            },
+            ErrorBannerMessage::ClickRetry => {
+                ctx.props().on_retry.emit(());
+                false
+            },
+            ErrorBannerMessage::ToggleDetails => {
+                self.show_details = !self.show_details;
+                true
+            }
+        }
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        // details are for the error they were opened on; new callbacks alone keep them open
+        if self.error != ctx.props().error {
+            self.error = ctx.props().error.clone();
+            self.show_details = false;
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let error = &ctx.props().error;
+        html! {
+            <div class="error-banner" role="alert">
+                <button class="error-banner-x-elem" title="Dismiss" onclick={ctx.link().callback(|_| ErrorBannerMessage::ClickX)}>
+                    {"X"}
+                </button>
+                <span class="error-banner-message">{error.summary()}</span>
+                {
+                    if error.is_retryable() {
+                        html!{
+                            <button class="error-banner-retry-elem" onclick={ctx.link().callback(|_| ErrorBannerMessage::ClickRetry)}>
+                                {"Retry"}
+                            </button>
+                        }
+                    } else {
+                        html!{}
+                    }
+                }
+                <div class="error-banner-details">
+                    <button class="error-banner-details-toggle-elem" onclick={ctx.link().callback(|_| ErrorBannerMessage::ToggleDetails)}>
+                        { if self.show_details { "Hide details" } else { "Show details" } }
+                    </button>
+                    {
+                        if self.show_details {
+                            html!{ <pre class="error-banner-details-text">{error.details()}</pre> }
+                        } else {
+                            html!{}
+                        }
+                    }
+                </div>
+            </div>
+        }
+    }
+}
@@ -1,7 +1,19 @@
+mod creator_page;
+mod error_banner;
+mod price;
 mod robot;
+mod robot_page;
 mod root;
+mod saved_searches;
 mod search;
+mod sparkline;
 
+pub use creator_page::CreatorPageComponent;
+pub use error_banner::ErrorBannerComponent;
+pub use price::PriceComponent;
 pub use robot::RobotComponent;
+pub use robot_page::RobotPageComponent;
 pub use root::RootComponent;
+pub use saved_searches::SavedSearchesComponent;
 pub use search::SearchComponent;
+pub use sparkline::SparklineComponent;
@@ -0,0 +1,50 @@
+use yew::{html, Component, Context, Html, Properties};
+use yew_icons::{Icon, IconId};
+
+use crate::api::{Currency, Price};
+
+#[derive(Properties, PartialEq)]
+pub struct PriceProperties {
+    pub price: Price,
+}
+
+/// One price with its currency icon
+pub struct PriceComponent;
+
+impl PriceComponent {
+    fn icon(currency: &Currency) -> IconId {
+        match currency {
+            Currency::Techpoints => IconId::BootstrapCoin,
+            Currency::Bloxcoins => IconId::LucideCurrency,
+            Currency::Unknown(_) => IconId::BootstrapCashCoin,
+        }
+    }
+}
+
+impl Component for PriceComponent {
+    type Message = ();
+    type Properties = PriceProperties;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self
+    }
+
+    fn update(&mut self, _ctx: &Context<Self>, _msg: Self::Message) -> bool {
+        false
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let price = &ctx.props().price;
+        let name = price.currency.name();
+        let class = match price.currency {
+            Currency::Unknown(_) => "bot-price bot-price-unknown",
+            _ => "bot-price",
+        };
+        html! {
+            <div class={class} title={format!("{} {}", price.formatted_amount(), name)}>
+                <span class="bot-price-icon"><Icon icon_id={Self::icon(&price.currency)} title={name} height={"1rem".to_owned()}/></span>
+                <span class="bot-price-number">{price.formatted_amount()}</span>
+            </div>
+        }
+    }
+}
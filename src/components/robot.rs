@@ -1,15 +1,62 @@
+use std::rc::Rc;
+
+use crf_common::Catalogue;
 use yew::{html, Component, Context, Html, Properties};
 use yew_icons::{Icon, IconId};
+use yew_router::prelude::*;
 
-use crate::api::ResultItem;
+use crate::api::{Price, ResultItem};
+use crate::route::Route;
+use super::PriceComponent;
 
 #[derive(Properties, PartialEq)]
 pub struct RobotProperties {
-    pub robot: ResultItem
+    pub robot: ResultItem,
+    pub catalogue: Rc<Catalogue>,
 }
 
 pub struct RobotComponent;
 
+/// `text` with the given byte ranges wrapped in `<mark>`; bad ranges are left unmarked
+fn marked(text: &str, ranges: Option<&Vec<(usize, usize)>>) -> Html {
+    let ranges = match ranges {
+        Some(ranges) if !ranges.is_empty() => ranges,
+        _ => return html!{ {text} },
+    };
+    let mut parts = Vec::new();
+    let mut at = 0;
+    for &(start, end) in ranges {
+        if start < at || end <= start || end > text.len() || !text.is_char_boundary(start) || !text.is_char_boundary(end) {
+            continue;
+        }
+        parts.push(html!{ {&text[at..start]} });
+        parts.push(html!{ <mark>{&text[start..end]}</mark> });
+        at = end;
+    }
+    parts.push(html!{ {&text[at..]} });
+    parts.into_iter().collect::<Html>()
+}
+
+impl RobotComponent {
+    fn block_groups(ctx: &Context<Self>, item: &ResultItem) -> Html {
+        html! {
+            <div class="bot-blocks">
+                {
+                    ctx.props().catalogue.group_blocks(&item.robot.blockCounts).iter().map(|group| {
+                        let (class, title) = match group.category {
+                            Some(_) => ("bot-blocks-group", group.name().to_owned()),
+                            None => ("bot-blocks-group bot-blocks-unknown", format!("{} block types missing from the catalogue", group.blocks.len())),
+                        };
+                        html!{
+                            <span class={class} title={title}>{format!("{} {}", group.name(), group.total())}</span>
+                        }
+                    }).collect::<Html>()
+                }
+            </div>
+        }
+    }
+}
+
 impl Component for RobotComponent {
     type Message = ();
     type Properties = RobotProperties;
@@ -27,16 +74,20 @@ impl Component for RobotComponent {
         let alt = format!("{} by {}", item.robot.name, item.robot.creatorName);
         html! {
             <div class="bot">
-                <div class="bot-image"> //style={format!("background-image: url('{}');", robot.image)}>
-                    <img alt={alt.clone()} src={item.robot.image.clone()} width="100%" title={alt}/>
-                </div>
-                <div class="bot-name">
-                    <span alt={item.robot.id.clone()}>{ &item.robot.name }</span>
-                </div>
-                <div class="bot-creator" key={item.robot.creatorId.clone()}>
-                    <span class="bot-creator-icon"><Icon icon_id={IconId::BootstrapBrush} title={"Creator"} height={"1.1rem".to_owned()}/></span>
-                    <span class="bot-creator-name" alt={item.robot.creatorId.clone()}>{&item.robot.creatorName}</span>
-                </div>
+                <Link<Route> to={Route::Robot { id: item.robot.id.clone() }} classes="bot-link">
+                    <div class="bot-image"> //style={format!("background-image: url('{}');", robot.image)}>
+                        <img alt={alt.clone()} src={item.robot.image.clone()} width="100%" title={alt}/>
+                    </div>
+                    <div class="bot-name">
+                        <span alt={item.robot.id.clone()}>{ marked(&item.robot.name, item.highlights.as_ref().map(|h| &h.name)) }</span>
+                    </div>
+                </Link<Route>>
+                <Link<Route> to={Route::Creator { id: item.robot.creatorId.clone() }} classes="bot-creator-link">
+                    <div class="bot-creator" key={item.robot.creatorId.clone()}>
+                        <span class="bot-creator-icon"><Icon icon_id={IconId::BootstrapBrush} title={"Creator"} height={"1.1rem".to_owned()}/></span>
+                        <span class="bot-creator-name" alt={item.robot.creatorId.clone()}>{ marked(&item.robot.creatorName, item.highlights.as_ref().map(|h| &h.creator_name)) }</span>
+                    </div>
+                </Link<Route>>
                 <div class="bot-cpu">
                     //<div class="bot-cpu-header">{"CPU"}</div>
                     <div class="bot-base-cpu">
@@ -58,23 +109,25 @@ impl Component for RobotComponent {
                         <span class="bot-cluster-number">{item.robot.clusterCount}</span>
                     </div>
                 </div>
-                /*<div class="bot-price-wrapper">
+                {
+                    // with no blocks in the catalogue, every group would be "Unknown"
+                    if ctx.props().catalogue.blocks.is_empty() {
+                        html!{}
+                    } else {
+                        Self::block_groups(ctx, item)
+                    }
+                }
+                <div class="bot-price-wrapper">
                     {
-                        item.prices.iter().enumerate().map(|(index, price)| {
-                            let (icon, name) = match index {
-                                0 => (IconId::BootstrapCoin, "Techpoints"),
-                                1 => (IconId::LucideCurrency, "Bloxcoins"),
-                                _ => (IconId::BootstrapCashCoin, "???"),
-                            };
-                            html!{
-                                <div class="bot-price">
-                                    <span class="bot-price-icon"><Icon icon_id={icon} title={name} height={"1rem".to_owned()}/></span>
-                                    <span class="bot-price-number">{price.amount}</span>
-                                </div>
-                            }
-                        }).collect::<Html>()
+                        if item.prices.is_empty() {
+                            html!{ <div class="bot-price bot-price-none">{"Not for sale"}</div> }
+                        } else {
+                            Price::by_currency(&item.prices).into_iter().map(|price| html!{
+                                <PriceComponent price={price}/>
+                            }).collect::<Html>()
+                        }
                     }
-                </div>*/
+                </div>
             </div>
         }
     }
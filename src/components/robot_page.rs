@@ -0,0 +1,401 @@
+use std::rc::Rc;
+
+use crf_common::{BlockInfo, Catalogue};
+use gloo_console as console;
+use yew::{html, Component, Context, Html, Properties};
+use yew_icons::{Icon, IconId};
+use yew_router::prelude::*;
+
+use crate::api::{ApiError, Currency, HistoryPoint, Price, ResultItem, SearchRequest, robot_history_query, robot_query};
+use crate::route::Route;
+use super::{ErrorBannerComponent, PriceComponent, SparklineComponent};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BlockColumn {
+    Id,
+    Count,
+}
+
+pub enum RobotPageMessage {
+    SortBlocks(BlockColumn),
+    Loaded(String, Result<ResultItem, ApiError>),
+    HistoryLoaded(String, Result<Vec<HistoryPoint>, ApiError>),
+    ClickErrorX,
+    ClickRetry,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct RobotPageProperties {
+    pub id: String,
+    /// The robot, if it's in the search results we already have; otherwise it's fetched
+    pub robot: Option<ResultItem>,
+    /// Search to go back to
+    pub back: Option<SearchRequest>,
+    pub catalogue: Rc<Catalogue>,
+}
+
+pub struct RobotPageComponent {
+    sort: BlockColumn,
+    descending: bool,
+    fetched: Option<ResultItem>,
+    loading: bool,
+    error: Option<ApiError>,
+    /// History of the robot with this ID; empty when crf_2b has none
+    history: Option<(String, Vec<HistoryPoint>)>,
+}
+
+impl RobotPageComponent {
+    /// Ask crf_2b for the robot when it wasn't passed in
+    fn fetch(&mut self, ctx: &Context<Self>) {
+        self.fetched = None;
+        self.error = None;
+        if ctx.props().robot.is_some() {
+            self.loading = false;
+            return;
+        }
+        self.loading = true;
+        let id = ctx.props().id.clone();
+        let callback = ctx.link().callback(|(id, result)| RobotPageMessage::Loaded(id, result));
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = robot_query(&id).await;
+            callback.emit((id, result));
+        });
+    }
+
+    /// Ask crf_2b how the robot changed over time. Missing history just means no charts.
+    fn fetch_history(&mut self, ctx: &Context<Self>) {
+        self.history = None;
+        let id = ctx.props().id.clone();
+        let callback = ctx.link().callback(|(id, result)| RobotPageMessage::HistoryLoaded(id, result));
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = robot_history_query(&id).await;
+            callback.emit((id, result));
+        });
+    }
+
+    fn history(&self) -> Html {
+        let points = match &self.history {
+            Some((_, points)) if !points.is_empty() => points,
+            _ => return html!{},
+        };
+        let series = |label: &str, value: fn(&HistoryPoint) -> Option<f64>| {
+            let values: Vec<(i64, f64)> = points.iter()
+                .filter_map(|point| Some((point.seen, value(point)?)))
+                .collect();
+            if values.is_empty() {
+                html!{}
+            } else {
+                html!{ <SparklineComponent label={label.to_owned()} points={values}/> }
+            }
+        };
+        let mut currencies: Vec<Currency> = Vec::new();
+        for price in points.iter().flat_map(|point| point.prices.iter()) {
+            if !currencies.contains(&price.currency) {
+                currencies.push(price.currency);
+            }
+        }
+        currencies.sort_by_key(Currency::code);
+        html! {
+            <div class="robot-page-section">
+                <div class="robot-page-section-header">{"History"}</div>
+                <div class="robot-page-history">
+                    {series("Views", |point| point.views.map(|views| views as f64))}
+                    {series("Base CPU", |point| Some(point.base_cpu as f64))}
+                    {series("Weapon CPU", |point| Some(point.weapon_cpu as f64))}
+                    {series("Cosmetic CPU", |point| Some(point.cosmetic_cpu as f64))}
+                    {series("Clusters", |point| Some(point.cluster_count as f64))}
+                    {
+                        currencies.into_iter().map(|currency| {
+                            // points where it wasn't for sale in this currency are left out
+                            let values: Vec<(i64, f64)> = points.iter()
+                                .filter_map(|point| {
+                                    point.prices.iter()
+                                        .find(|price| price.currency == currency)
+                                        .map(|price| (point.seen, price.amount as f64))
+                                })
+                                .collect();
+                            html!{ <SparklineComponent label={currency.name()} points={values}/> }
+                        }).collect::<Html>()
+                    }
+                </div>
+            </div>
+        }
+    }
+
+    fn back_link(ctx: &Context<Self>) -> Html {
+        html! {
+            <div class="robot-page-back">
+                <Link<Route, SearchRequest> to={Route::Search} query={ctx.props().back.clone()}>
+                    {"< Back to results"}
+                </Link<Route, SearchRequest>>
+            </div>
+        }
+    }
+
+    fn sort_header(&self, ctx: &Context<Self>, column: BlockColumn, name: &'static str) -> Html {
+        let arrow = if self.sort != column {
+            ""
+        } else if self.descending {
+            " v"
+        } else {
+            " ^"
+        };
+        html! {
+            <th class="robot-page-blocks-header" onclick={ctx.link().callback(move |_| RobotPageMessage::SortBlocks(column))}>
+                {name}{arrow}
+            </th>
+        }
+    }
+
+    /// `catalogued` is false while the catalogue names no blocks at all
+    fn block_name(id: usize, info: Option<&BlockInfo>, catalogued: bool) -> Html {
+        match info {
+            Some(info) => html! {
+                <span class="robot-page-block-name" title={format!("Block {}", id)}>
+                    {
+                        match &info.icon {
+                            Some(icon) => html!{ <img class="catalogue-icon" alt="" src={icon.clone()}/> },
+                            None => html!{},
+                        }
+                    }
+                    {&info.name}
+                </span>
+            },
+            None if !catalogued => html! {
+                <span class="robot-page-block-name">{format!("Block {}", id)}</span>
+            },
+            None => html! {
+                <span class="catalogue-unknown" title="Not in the block catalogue">{format!("Unknown block {}", id)}</span>
+            },
+        }
+    }
+
+    fn block_table(&self, ctx: &Context<Self>, item: &ResultItem) -> Html {
+        let catalogued = !ctx.props().catalogue.blocks.is_empty();
+        let mut groups = ctx.props().catalogue.group_blocks(&item.robot.blockCounts);
+        for group in groups.iter_mut() {
+            match self.sort {
+                BlockColumn::Id => group.blocks.sort_by_key(|(id, _, _)| *id),
+                BlockColumn::Count => group.blocks.sort_by_key(|(id, _, count)| (*count, *id)),
+            }
+            if self.descending {
+                group.blocks.reverse();
+            }
+        }
+        html! {
+            <table class="robot-page-blocks">
+                <thead>
+                    <tr>
+                        {self.sort_header(ctx, BlockColumn::Id, "Block")}
+                        {self.sort_header(ctx, BlockColumn::Count, "Count")}
+                    </tr>
+                </thead>
+                {
+                    groups.iter().map(|group| html!{
+                        <tbody key={group.name()}>
+                            {
+                                // without a catalogue, the one group is every block
+                                if catalogued {
+                                    html!{
+                                        <tr class="robot-page-blocks-category">
+                                            <th>{group.name()}</th>
+                                            <th>{group.total()}</th>
+                                        </tr>
+                                    }
+                                } else {
+                                    html!{}
+                                }
+                            }
+                            {
+                                group.blocks.iter().map(|(id, info, count)| html!{
+                                    <tr key={id.to_string()}>
+                                        <td>{Self::block_name(*id, *info, catalogued)}</td>
+                                        <td>{count}</td>
+                                    </tr>
+                                }).collect::<Html>()
+                            }
+                        </tbody>
+                    }).collect::<Html>()
+                }
+            </table>
+        }
+    }
+
+    fn material(ctx: &Context<Self>, id: usize) -> Html {
+        match ctx.props().catalogue.materials.get(&id) {
+            Some(info) => html! {
+                <li title={format!("Material {}", id)}>
+                    {
+                        match &info.icon {
+                            Some(icon) => html!{ <img class="catalogue-icon" alt="" src={icon.clone()}/> },
+                            None => html!{},
+                        }
+                    }
+                    {&info.name}
+                </li>
+            },
+            None if ctx.props().catalogue.materials.is_empty() => html! {
+                <li>{format!("Material {}", id)}</li>
+            },
+            None => html! {
+                <li class="catalogue-unknown" title="Not in the material catalogue">{format!("Unknown material {}", id)}</li>
+            },
+        }
+    }
+}
+
+impl Component for RobotPageComponent {
+    type Message = RobotPageMessage;
+    type Properties = RobotPageProperties;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let mut page = Self {
+            sort: BlockColumn::Count,
+            descending: true,
+            fetched: None,
+            loading: false,
+            error: None,
+            history: None,
+        };
+        page.fetch(ctx);
+        page.fetch_history(ctx);
+        page
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            RobotPageMessage::SortBlocks(column) => {
+                if self.sort == column {
+                    self.descending = !self.descending;
+                } else {
+                    self.sort = column;
+                    self.descending = column == BlockColumn::Count;
+                }
+            },
+            RobotPageMessage::Loaded(id, result) => {
+                // ignore answers for a robot we've navigated away from
+                if id != ctx.props().id {
+                    return false;
+                }
+                self.loading = false;
+                match result {
+                    Ok(item) => self.fetched = Some(item),
+                    Err(e) => self.error = Some(e),
+                }
+            },
+            RobotPageMessage::HistoryLoaded(id, result) => {
+                if id != ctx.props().id {
+                    return false;
+                }
+                match result {
+                    Ok(points) => self.history = Some((id, points)),
+                    Err(e) => {
+                        console::log!("No robot history:", e.details());
+                        self.history = Some((id, Vec::new()));
+                    },
+                }
+            },
+            RobotPageMessage::ClickErrorX => self.error = None,
+            RobotPageMessage::ClickRetry => self.fetch(ctx),
+        }
+        true
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        let have = self.fetched.as_ref().map(|item| item.robot.id == ctx.props().id).unwrap_or(false);
+        if !have {
+            self.fetch(ctx);
+        }
+        let have_history = self.history.as_ref().map(|(id, _)| *id == ctx.props().id).unwrap_or(false);
+        if !have_history {
+            self.fetch_history(ctx);
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let item = match ctx.props().robot.as_ref().or(self.fetched.as_ref()) {
+            Some(item) => item,
+            None => return html! {
+                <div class="robot-page">
+                    {Self::back_link(ctx)}
+                    {
+                        if let Some(e) = &self.error {
+                            html!{
+                                <ErrorBannerComponent
+                                    error={e.clone()}
+                                    on_dismiss={ctx.link().callback(|_| RobotPageMessage::ClickErrorX)}
+                                    on_retry={ctx.link().callback(|_| RobotPageMessage::ClickRetry)}/>
+                            }
+                        } else if self.loading {
+                            html!{ <div class="bot-empty">{"Loading..."}</div> }
+                        } else {
+                            html!{
+                                <div class="bot-empty">
+                                    {format!("Robot {} hasn't been seen in any search yet", ctx.props().id)}
+                                </div>
+                            }
+                        }
+                    }
+                </div>
+            },
+        };
+        let robot = &item.robot;
+        let alt = format!("{} by {}", robot.name, robot.creatorName);
+        html! {
+            <div class="robot-page">
+                {Self::back_link(ctx)}
+                <div class="robot-page-image">
+                    <img alt={alt.clone()} src={robot.image.clone()} width="100%" title={alt}/>
+                </div>
+                <div class="robot-page-info">
+                    <div class="bot-name">{&robot.name}</div>
+                    <div class="bot-creator">
+                        <span class="bot-creator-icon"><Icon icon_id={IconId::BootstrapBrush} title={"Creator"} height={"1.1rem".to_owned()}/></span>
+                        <span class="bot-creator-name">
+                            <Link<Route> to={Route::Creator { id: robot.creatorId.clone() }}>{&robot.creatorName}</Link<Route>>
+                        </span>
+                    </div>
+                    <table class="robot-page-metadata">
+                        <tr><th>{"Robot ID"}</th><td>{&robot.id}</td></tr>
+                        <tr><th>{"Creator ID"}</th><td>{&robot.creatorId}</td></tr>
+                        <tr><th>{"Base CPU"}</th><td>{robot.baseCpu}</td></tr>
+                        <tr><th>{"Weapon CPU"}</th><td>{robot.weaponCpu}</td></tr>
+                        <tr><th>{"Cosmetic CPU"}</th><td>{robot.cosmeticCpu}</td></tr>
+                        <tr><th>{"Clusters"}</th><td>{robot.clusterCount}</td></tr>
+                        <tr><th>{"Block types"}</th><td>{robot.blockCounts.len()}</td></tr>
+                        <tr><th>{"Blocks"}</th><td>{robot.blockCounts.values().sum::<usize>()}</td></tr>
+                    </table>
+                    <div class="robot-page-section">
+                        <div class="robot-page-section-header">{"Prices"}</div>
+                        {
+                            if item.prices.is_empty() {
+                                html!{ <div>{"Not for sale"}</div> }
+                            } else {
+                                Price::by_currency(&item.prices).into_iter().map(|price| html!{
+                                    <div class="robot-page-price">
+                                        <PriceComponent price={price.clone()}/>
+                                        <span class="robot-page-price-currency">{price.currency.name()}</span>
+                                    </div>
+                                }).collect::<Html>()
+                            }
+                        }
+                    </div>
+                    <div class="robot-page-section">
+                        <div class="robot-page-section-header">{"Materials"}</div>
+                        <ul class="robot-page-materials">
+                            {
+                                robot.materialsUsed.iter().map(|material| Self::material(ctx, *material)).collect::<Html>()
+                            }
+                        </ul>
+                    </div>
+                    <div class="robot-page-section">
+                        <div class="robot-page-section-header">{"Blocks"}</div>
+                        {self.block_table(ctx, item)}
+                    </div>
+                    {self.history()}
+                </div>
+            </div>
+        }
+    }
+}
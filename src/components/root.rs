@@ -1,54 +1,197 @@
+use std::rc::Rc;
+
+use crf_common::Catalogue;
 use gloo_console as console;
-use yew::{html, Component, Context, Html};
+use wasm_bindgen::JsValue;
+use yew::{html, Callback, Component, Context, Html};
+use yew_router::prelude::*;
+
+use crate::api::{FullTextInfo, PostFilterInfo, ResultItem, ResultSource, SearchRequest, SearchResults, catalogue_query};
+use crate::route::Route;
+use super::{CreatorPageComponent, RobotComponent, RobotPageComponent, SearchComponent};
 
-use crate::api::{ResultItem, SearchResults};
-use super::{RobotComponent, SearchComponent};
+pub enum RootMessage {
+    Search(SearchRequest),
+    Results(SearchResults),
+    Catalogue(Catalogue),
+}
 
 pub struct RootComponent {
     results: Vec<ResultItem>,
+    post_filter: Option<PostFilterInfo>,
+    /// Results are from crf_2b's archive rather than the CRF
+    archived: bool,
+    /// When the archive was last updated
+    archived_at: Option<i64>,
+    full_text: Option<FullTextInfo>,
+    last_search: Option<SearchRequest>,
+    /// Empty until loaded; block names and categories are left out while it has none
+    catalogue: Rc<Catalogue>,
 }
 
-impl Component for RootComponent {
-    type Message = SearchResults;
-    type Properties = ();
+impl RootComponent {
+    fn post_filter_notice(info: &Option<PostFilterInfo>) -> Html {
+        match info {
+            Some(info) => html! {
+                <div class="search-post-filter">
+                    {format!("Filtered by this site from {} robots on {} CRF pages", info.scanned_robots, info.scanned_pages)}
+                    {
+                        if info.complete {
+                            html!{}
+                        } else {
+                            html!{ <span class="search-post-filter-incomplete">{" - stopped early, there may be more matches"}</span> }
+                        }
+                    }
+                </div>
+            },
+            None => html!{},
+        }
+    }
 
-    fn create(_ctx: &Context<Self>) -> Self {
-        Self {
-            results: vec![],
+    fn full_text_notice(info: &Option<FullTextInfo>) -> Html {
+        match info {
+            Some(info) => html! {
+                <div class="search-post-filter">
+                    {"Found by this site's search of the robots it has seen, not by the CRF"}
+                    {
+                        if info.complete {
+                            html!{}
+                        } else {
+                            html!{ <span class="search-post-filter-incomplete">{format!(" - only the best {} matches are shown, try more words", info.hits)}</span> }
+                        }
+                    }
+                </div>
+            },
+            None => html!{},
         }
     }
 
-    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
-        self.results = msg.results;
-        console::log!("Got search results");
-        true
+    fn archived_notice(archived: bool, archived_at: Option<i64>) -> Html {
+        if !archived {
+            return html!{};
+        }
+        let date = match archived_at {
+            Some(secs) => {
+                let date = js_sys::Date::new(&JsValue::from_f64(secs as f64 * 1000.0));
+                String::from(date.to_date_string())
+            },
+            None => "an unknown date".to_owned(),
+        };
+        html! {
+            <div class="search-archived">
+                {format!("Showing archived data from {}", date)}
+            </div>
+        }
     }
 
-    fn view(&self, ctx: &Context<Self>) -> Html {
+    fn search_page(results: &[ResultItem], post_filter: &Option<PostFilterInfo>, full_text: &Option<FullTextInfo>, archived: (bool, Option<i64>), catalogue: &Rc<Catalogue>, on_search: Callback<SearchRequest>, on_results: Callback<SearchResults>) -> Html {
         html! {
-            <div>
+            <>
                 <div class="search-wrapper">
-                    <SearchComponent on_results={ctx.link().callback(|x| x)}/>
+                    <SearchComponent on_search={on_search} on_results={on_results}/>
                 </div>
+                {Self::archived_notice(archived.0, archived.1)}
+                {Self::post_filter_notice(post_filter)}
+                {Self::full_text_notice(full_text)}
                 <div class="bot-wrapper">{
-                    if self.results.is_empty() {
+                    if results.is_empty() {
                         html!{
                             <div class="bot-empty">
                                 {"Search for robots from the CRF2"}
                             </div>
                         }
                     } else {
-                        self.results.iter().map(|bot| {
-                            html!{ <RobotComponent robot={bot.clone()} key={bot.robot.id.clone()}/> }
+                        results.iter().map(|bot| {
+                            html!{ <RobotComponent robot={bot.clone()} catalogue={catalogue.clone()} key={bot.robot.id.clone()}/> }
                         }).collect::<Html>()
                     }
                 }</div>
-                <div class="footer">
-                    {"Unofficial CRF2 site by "}<a href="https://github.com/NGnius">{"NGnius"}</a>
-                    {" | "}
-                    {"API by "}<a href="https://www.freejamgames.com/">{"FreeJam"}</a>{" through "}<a href="https://github.com/NGnius/libfj">{"libfj"}</a>
+            </>
+        }
+    }
+}
+
+impl Component for RootComponent {
+    type Message = RootMessage;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let callback = ctx.link().callback(RootMessage::Catalogue);
+        wasm_bindgen_futures::spawn_local(async move {
+            match catalogue_query().await {
+                Ok(catalogue) => callback.emit(catalogue),
+                Err(e) => console::log!("Failed to load block catalogue:", e.details()),
+            }
+        });
+        Self {
+            results: vec![],
+            post_filter: None,
+            archived: false,
+            archived_at: None,
+            full_text: None,
+            last_search: None,
+            catalogue: Rc::new(Catalogue::default()),
+        }
+    }
+
+    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            RootMessage::Search(req) => {
+                self.last_search = Some(req);
+                false
+            },
+            RootMessage::Results(results) => {
+                self.results = results.results;
+                self.post_filter = results.post_filter;
+                self.archived = results.source == ResultSource::Cache;
+                self.archived_at = results.archived_at;
+                self.full_text = results.full_text;
+                console::log!("Got search results");
+                true
+            },
+            RootMessage::Catalogue(catalogue) => {
+                self.catalogue = Rc::new(catalogue);
+                true
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let results = self.results.clone();
+        let post_filter = self.post_filter.clone();
+        let full_text = self.full_text.clone();
+        let archived = (self.archived, self.archived_at);
+        let last_search = self.last_search.clone();
+        let catalogue = self.catalogue.clone();
+        let on_search = ctx.link().callback(RootMessage::Search);
+        let on_results = ctx.link().callback(RootMessage::Results);
+        let switch = move |route: &Route| match route {
+            Route::Search | Route::NotFound => Self::search_page(&results, &post_filter, &full_text, archived, &catalogue, on_search.clone(), on_results.clone()),
+            Route::Robot { id } => html! {
+                <RobotPageComponent
+                    id={id.clone()}
+                    robot={results.iter().find(|item| &item.robot.id == id).cloned()}
+                    back={last_search.clone()}
+                    catalogue={catalogue.clone()}/>
+            },
+            Route::Creator { id } => html! {
+                <CreatorPageComponent
+                    id={id.clone()}
+                    back={last_search.clone()}
+                    catalogue={catalogue.clone()}/>
+            },
+        };
+        html! {
+            <BrowserRouter>
+                <div>
+                    <Switch<Route> render={Switch::render(switch)}/>
+                    <div class="footer">
+                        {"Unofficial CRF2 site by "}<a href="https://github.com/NGnius">{"NGnius"}</a>
+                        {" | "}
+                        {"API by "}<a href="https://www.freejamgames.com/">{"FreeJam"}</a>{" through "}<a href="https://github.com/NGnius/libfj">{"libfj"}</a>
+                    </div>
                 </div>
-            </div>
+            </BrowserRouter>
         }
     }
 }
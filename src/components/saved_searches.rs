@@ -0,0 +1,345 @@
+use gloo_console as console;
+use gloo_storage::{LocalStorage, Storage};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlInputElement, HtmlTextAreaElement};
+use yew::{html, Callback, Component, Context, Html, NodeRef, Properties, events::{Event, FocusEvent, InputEvent, KeyboardEvent}};
+
+use crate::api::SearchRequest;
+
+const SAVED_SEARCHES_KEY: &str = "crf_tyew.search.saved";
+/// Bumped when the export format changes incompatibly
+const EXPORT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct SavedSearch {
+    pub name: String,
+    pub search: SearchRequest,
+}
+
+/// Saved searches as exported, for sharing between browsers
+#[derive(Serialize, Deserialize)]
+struct SavedSearchesExport {
+    version: u32,
+    searches: Vec<SavedSearch>,
+}
+
+pub enum SavedSearchesMessage {
+    SetName(String),
+    Save,
+    Run(usize),
+    StartRename(usize),
+    Rename(usize, String),
+    /// Stop renaming without changing the name
+    CancelRename,
+    Move(usize, isize),
+    Delete(usize),
+    ToggleTransfer,
+    SetImport(String),
+    Import,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct SavedSearchesProperties {
+    /// The search in the form, which is what gets saved
+    pub current: SearchRequest,
+    pub on_run: Callback<SearchRequest>,
+}
+
+/// Named searches kept in this browser's local storage
+pub struct SavedSearchesComponent {
+    searches: Vec<SavedSearch>,
+    name: String,
+    renaming: Option<usize>,
+    rename_input: NodeRef,
+    /// The rename input was just shown and should get focus
+    focus_rename: bool,
+    /// Import/export panel is open
+    transfer_open: bool,
+    import: String,
+    import_error: Option<String>,
+}
+
+impl SavedSearchesComponent {
+    fn load() -> Vec<SavedSearch> {
+        match LocalStorage::get(SAVED_SEARCHES_KEY) {
+            Ok(searches) => searches,
+            Err(e) => {
+                console::log!("No saved searches:", e.to_string());
+                Vec::new()
+            }
+        }
+    }
+
+    fn store(&self) {
+        if let Err(e) = LocalStorage::set(SAVED_SEARCHES_KEY, &self.searches) {
+            console::log!("Failed to save searches:", e.to_string());
+        }
+    }
+
+    /// `name`, or the first of `name (2)`, `name (3)`, ... that no other saved search has.
+    /// `except` is a search whose own name doesn't count, when renaming it.
+    fn unique_name(&self, name: &str, except: Option<usize>) -> String {
+        let taken = |candidate: &str| self.searches.iter().enumerate()
+            .any(|(index, saved)| Some(index) != except && saved.name == candidate);
+        if !taken(name) {
+            return name.to_owned();
+        }
+        (2..).map(|n| format!("{} ({})", name, n))
+            .find(|candidate| !taken(candidate))
+            .unwrap()
+    }
+
+    fn export(&self) -> String {
+        let export = SavedSearchesExport {
+            version: EXPORT_VERSION,
+            searches: self.searches.clone(),
+        };
+        serde_json::to_string_pretty(&export).unwrap()
+    }
+
+    fn parse_import(text: &str) -> Result<Vec<SavedSearch>, String> {
+        let export: SavedSearchesExport = serde_json::from_str(text)
+            .map_err(|e| format!("Not a saved search export: {}", e))?;
+        if export.version != EXPORT_VERSION {
+            return Err(format!("Unsupported export version {}", export.version));
+        }
+        if let Some(invalid) = export.searches.iter().find(|saved| !saved.search.validate().is_empty()) {
+            return Err(format!("Search \"{}\" is invalid", invalid.name));
+        }
+        Ok(export.searches)
+    }
+
+    fn entry(&self, ctx: &Context<Self>, index: usize, saved: &SavedSearch) -> Html {
+        let last = index + 1 == self.searches.len();
+        let name = if self.renaming == Some(index) {
+            // Enter (or leaving a changed input) renames; Escape or leaving it unchanged doesn't
+            html! {
+                <input type="text" class="saved-search-rename" value={saved.name.clone()} ref={self.rename_input.clone()}
+                    onchange={
+                        ctx.link().callback(move |e: Event| {
+                            let target = e.target().unwrap()
+                                .unchecked_into::<HtmlInputElement>();
+                            SavedSearchesMessage::Rename(index, target.value())
+                        })
+                    }
+                    onblur={ctx.link().callback(|_: FocusEvent| SavedSearchesMessage::CancelRename)}
+                    onkeydown={
+                        ctx.link().batch_callback(|e: KeyboardEvent| {
+                            (e.key() == "Escape").then(|| SavedSearchesMessage::CancelRename)
+                        })
+                    }/>
+            }
+        } else {
+            html! {
+                <button class="saved-search-run" title="Run this search"
+                    onclick={ctx.link().callback(move |_| SavedSearchesMessage::Run(index))}>
+                    {&saved.name}
+                </button>
+            }
+        };
+        html! {
+            <li class="saved-search" key={saved.name.clone()}>
+                {name}
+                <span class="saved-search-actions">
+                    <button title="Rename" onclick={ctx.link().callback(move |_| SavedSearchesMessage::StartRename(index))}>{"Rename"}</button>
+                    <button title="Move up" disabled={index == 0} onclick={ctx.link().callback(move |_| SavedSearchesMessage::Move(index, -1))}>{"^"}</button>
+                    <button title="Move down" disabled={last} onclick={ctx.link().callback(move |_| SavedSearchesMessage::Move(index, 1))}>{"v"}</button>
+                    <button title="Delete" onclick={ctx.link().callback(move |_| SavedSearchesMessage::Delete(index))}>{"x"}</button>
+                </span>
+            </li>
+        }
+    }
+
+    fn transfer(&self, ctx: &Context<Self>) -> Html {
+        if !self.transfer_open {
+            return html!{};
+        }
+        html! {
+            <div class="saved-searches-transfer">
+                <label for="saved-searches-export" class="search-label">{"Export (copy this)"}</label>
+                <textarea id="saved-searches-export" class="saved-searches-json" readonly=true value={self.export()}/>
+                <label for="saved-searches-import" class="search-label">{"Import (paste an export, added to the end)"}</label>
+                <textarea id="saved-searches-import" class="saved-searches-json" value={self.import.clone()}
+                    oninput={
+                        ctx.link().callback(|e: InputEvent| {
+                            let target = e.target().unwrap()
+                                .unchecked_into::<HtmlTextAreaElement>();
+                            SavedSearchesMessage::SetImport(target.value())
+                        })
+                    }/>
+                {
+                    match &self.import_error {
+                        Some(e) => html!{ <div class="search-field-error">{e}</div> },
+                        None => html!{},
+                    }
+                }
+                <button class="saved-searches-import-button" disabled={self.import.trim().is_empty()}
+                    onclick={ctx.link().callback(|_| SavedSearchesMessage::Import)}>
+                    {"Import"}
+                </button>
+            </div>
+        }
+    }
+}
+
+impl Component for SavedSearchesComponent {
+    type Message = SavedSearchesMessage;
+    type Properties = SavedSearchesProperties;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        let mut component = Self {
+            searches: Vec::new(),
+            name: String::new(),
+            renaming: None,
+            rename_input: NodeRef::default(),
+            focus_rename: false,
+            transfer_open: false,
+            import: String::new(),
+            import_error: None,
+        };
+        // names are list keys; older versions could save the same name twice
+        for mut saved in Self::load() {
+            saved.name = component.unique_name(&saved.name, None);
+            component.searches.push(saved);
+        }
+        component
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            SavedSearchesMessage::SetName(name) => {
+                // only the Save button depends on it
+                let was_empty = self.name.trim().is_empty();
+                self.name = name;
+                return was_empty != self.name.trim().is_empty();
+            },
+            SavedSearchesMessage::Save => {
+                let name = self.name.trim().to_owned();
+                if name.is_empty() {
+                    return false;
+                }
+                let name = self.unique_name(&name, None);
+                self.searches.push(SavedSearch { name, search: ctx.props().current.clone() });
+                self.name.clear();
+                self.store();
+            },
+            SavedSearchesMessage::Run(index) => {
+                if let Some(saved) = self.searches.get(index) {
+                    console::log!("Run saved search:", &saved.name);
+                    ctx.props().on_run.emit(saved.search.clone());
+                }
+                return false;
+            },
+            SavedSearchesMessage::StartRename(index) => {
+                self.renaming = Some(index);
+                self.focus_rename = true;
+            },
+            SavedSearchesMessage::Rename(index, name) => {
+                // a change can arrive after the rename was already cancelled
+                if self.renaming != Some(index) {
+                    return false;
+                }
+                self.renaming = None;
+                let name = name.trim();
+                if !name.is_empty() && index < self.searches.len() {
+                    self.searches[index].name = self.unique_name(name, Some(index));
+                    self.store();
+                }
+            },
+            SavedSearchesMessage::CancelRename => {
+                if self.renaming.is_none() {
+                    return false;
+                }
+                self.renaming = None;
+            },
+            SavedSearchesMessage::Move(index, by) => {
+                let target = index as isize + by;
+                if target < 0 || target as usize >= self.searches.len() || index >= self.searches.len() {
+                    return false;
+                }
+                self.searches.swap(index, target as usize);
+                self.renaming = None;
+                self.store();
+            },
+            SavedSearchesMessage::Delete(index) => {
+                if index < self.searches.len() {
+                    self.searches.remove(index);
+                    self.renaming = None;
+                    self.store();
+                }
+            },
+            SavedSearchesMessage::ToggleTransfer => self.transfer_open = !self.transfer_open,
+            SavedSearchesMessage::SetImport(text) => {
+                self.import = text;
+                self.import_error = None;
+            },
+            SavedSearchesMessage::Import => match Self::parse_import(&self.import) {
+                Ok(searches) => {
+                    console::log!("Imported saved searches:", searches.len());
+                    for mut saved in searches {
+                        saved.name = self.unique_name(&saved.name, None);
+                        self.searches.push(saved);
+                    }
+                    self.import.clear();
+                    self.import_error = None;
+                    self.store();
+                },
+                Err(e) => self.import_error = Some(e),
+            },
+        }
+        true
+    }
+
+    fn rendered(&mut self, _ctx: &Context<Self>, _first_render: bool) {
+        if self.focus_rename {
+            self.focus_rename = false;
+            if let Some(input) = self.rename_input.cast::<HtmlInputElement>() {
+                let _ = input.focus();
+                input.select();
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let valid = ctx.props().current.validate().is_empty();
+        html! {
+            <div class="saved-searches">
+                <div class="search-label">{"Saved searches"}</div>
+                {
+                    if self.searches.is_empty() {
+                        html!{ <div class="saved-searches-empty">{"Name a search below to save it in this browser"}</div> }
+                    } else {
+                        html!{
+                            <ul class="saved-searches-list">
+                                {
+                                    self.searches.iter().enumerate()
+                                        .map(|(index, saved)| self.entry(ctx, index, saved))
+                                        .collect::<Html>()
+                                }
+                            </ul>
+                        }
+                    }
+                }
+                <div class="saved-searches-save">
+                    <input type="text" class="search-input-text-elem" placeholder="Name for this search"
+                        value={self.name.clone()}
+                        oninput={
+                            ctx.link().callback(|e: InputEvent| {
+                                let target = e.target().unwrap()
+                                    .unchecked_into::<HtmlInputElement>();
+                                SavedSearchesMessage::SetName(target.value())
+                            })
+                        }/>
+                    <button disabled={!valid || self.name.trim().is_empty()} onclick={ctx.link().callback(|_| SavedSearchesMessage::Save)}>
+                        {"Save"}
+                    </button>
+                    <button onclick={ctx.link().callback(|_| SavedSearchesMessage::ToggleTransfer)}>
+                        { if self.transfer_open { "Hide import/export" } else { "Import/export" } }
+                    </button>
+                </div>
+                {self.transfer(ctx)}
+            </div>
+        }
+    }
+}
@@ -1,52 +1,337 @@
+use std::collections::HashMap;
+
 use gloo_console as console;
+use gloo_storage::{LocalStorage, Storage};
 use wasm_bindgen::JsCast;
-use yew::{html, Component, Context, Html, Properties, Callback, events::Event};
-use web_sys::HtmlInputElement;
+use yew::{html, Component, Context, Html, Properties, Callback, events::{Event, InputEvent}};
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew_router::history::{History, HistoryListener, Location};
+use yew_router::scope_ext::RouterScopeExt;
 
-use crate::api::{SearchResults, SearchRequest, search_query};
+use crate::api::{ApiError, CreatorSuggestion, FieldError, SearchResults, SearchRequest, creators_query, search_query, date_input_to_upstream, upstream_to_date_input, is_valid_guid, MAX_CREATORS};
+use crate::route::Route;
+use super::{ErrorBannerComponent, SavedSearchesComponent};
 
 pub enum ChangeMessage {
     NoOp,
     ClickSearchButton,
     ClickErrorX,
-    SearchError(String),
-    SetText(String),
-    SetBaseMinimumCpu(isize),
-    SetBaseMaximumCpu(isize),
-    SetWeaponMinimumCpu(isize),
-    SetWeaponMaximumCpu(isize),
-    SetCosmeticMinimumCpu(isize),
-    SetCosmeticMaximumCpu(isize),
-    SetClusterMinimum(isize),
-    SetClusterMaximum(isize),
-    SetDateMinimum(String),
-    SetDateMaximum(String),
-    SetPage(isize),
-    SetCount(isize),
+    ClickRetry,
+    SearchError(ApiError),
+    SetText(Option<String>),
+    SetBaseMinimumCpu(Option<isize>),
+    SetBaseMaximumCpu(Option<isize>),
+    SetWeaponMinimumCpu(Option<isize>),
+    SetWeaponMaximumCpu(Option<isize>),
+    SetCosmeticMinimumCpu(Option<isize>),
+    SetCosmeticMaximumCpu(Option<isize>),
+    SetClusterMinimum(Option<isize>),
+    SetClusterMaximum(Option<isize>),
+    SetDateMinimum(Option<String>),
+    SetDateMaximum(Option<String>),
+    SetPage(Option<isize>),
+    SetCount(Option<isize>),
     SetSortBy(String),
     SetOrderBy(String),
+    SetPriceMinimum(Option<isize>),
+    SetPriceMaximum(Option<isize>),
+    SetPriceCurrency(String),
+    SetBlocksIncluded(Option<String>),
+    SetBlocksExcluded(Option<String>),
+    SetMaterialsIncluded(Option<String>),
+    SetMaterialsExcluded(Option<String>),
+    SetFullText(bool),
+    CreatorInput(String),
+    CreatorSuggestions(String, Result<Vec<CreatorSuggestion>, ApiError>),
+    /// Add a creator by GUID, with their name if known
+    AddCreator(String, Option<String>),
+    RemoveCreator(String),
+    RunSaved(SearchRequest),
+    ToggleAdvanced,
+    UrlChanged,
     Clear,
 }
 
+const ADVANCED_OPEN_KEY: &str = "crf_tyew.search.advanced_open";
+
+const SORT_NAMES: &[(&str, &str)] = &[
+    ("default", "Default"),
+    ("cpuPower", "CPU"),
+    ("cpuWeapon", "Weapon CPU"),
+    ("cpuCosmetic", "Cosmetic CPU"),
+    ("price", "Price"),
+    ("date", "Date"),
+    ("clusterCount", "Clusters"),
+    ("views", "Views"),
+];
+
+const ORDER_NAMES: &[(&str, &str)] = &[
+    ("ascending", "Ascending"),
+    ("descending", "Descending"),
+];
+
+const CURRENCY_NAMES: &[(&str, &str)] = &[
+    ("", "Any currency"),
+    ("0", "Techpoints"),
+    ("1", "Bloxcoins"),
+];
+
 #[derive(Properties, PartialEq)]
 pub struct SearchProperties {
+    /// Called with every search that's sent
+    pub on_search: Callback<SearchRequest>,
     pub on_results: Callback<SearchResults>,
 }
 
 pub struct SearchComponent {
     request: SearchRequest,
-    error: Option<String>,
+    last_request: Option<SearchRequest>,
+    error: Option<ApiError>,
+    advanced_open: bool,
+    /// What's typed in the creator field
+    creator_input: String,
+    creator_suggestions: Vec<CreatorSuggestion>,
+    /// Names of creators picked from suggestions; the search itself only has GUIDs
+    creator_names: HashMap<String, String>,
+    _history_listener: Option<HistoryListener>,
+}
+
+impl SearchComponent {
+    /// Inline message for the first problem with `field`, if any
+    fn field_error(errors: &[FieldError], field: &str) -> Html {
+        match errors.iter().find(|e| e.field == field) {
+            Some(e) => html!{ <div class="search-field-error">{&e.message}</div> },
+            None => html!{},
+        }
+    }
+
+    /// Number input driven by `value`; clearing it unsets the field
+    fn number_input(ctx: &Context<Self>, errors: &[FieldError], field: &'static str, label: &'static str, min: &'static str, value: Option<isize>, msg: fn(Option<isize>) -> ChangeMessage) -> Html {
+        html! {
+            <div class="search-input-number">
+                <label for={field} class="search-label">{label}</label>
+                <input type="number" id={field} class="search-input-number-elem" min={min}
+                    value={value.map(|x| x.to_string()).unwrap_or_default()}
+                    onchange={
+                        ctx.link().callback(move |e: Event| {
+                            let target = e.target().unwrap()
+                                .unchecked_into::<HtmlInputElement>();
+                            let text = target.value();
+                            if text.trim().is_empty() {
+                                return msg(None);
+                            }
+                            match text.trim().parse() {
+                                Ok(val) => msg(Some(val)),
+                                Err(e) => {
+                                    console::log!(field, "parse err:", e.to_string());
+                                    // the field is unchanged, so a re-render wouldn't touch the input;
+                                    // put the last good value back by hand
+                                    target.set_value(&value.map(|x| x.to_string()).unwrap_or_default());
+                                    ChangeMessage::NoOp
+                                }
+                            }
+                        })
+                    }/>
+                {Self::field_error(errors, field)}
+            </div>
+        }
+    }
+
+    /// Text input driven by `value`; blank unsets the field
+    fn text_input(ctx: &Context<Self>, errors: &[FieldError], field: &'static str, label: &'static str, placeholder: &'static str, value: &Option<String>, msg: fn(Option<String>) -> ChangeMessage) -> Html {
+        html! {
+            <div class="search-input-text">
+                <label for={field} class="search-label">{label}</label>
+                <input type="text" id={field} class="search-input-text-elem" placeholder={placeholder}
+                    value={value.clone().unwrap_or_default()}
+                    onchange={
+                        ctx.link().callback(move |e: Event| {
+                            let target = e.target().unwrap()
+                                .unchecked_into::<HtmlInputElement>();
+                            let value = target.value().trim().to_owned();
+                            msg(if value.is_empty() { None } else { Some(value) })
+                        })
+                    }/>
+                {Self::field_error(errors, field)}
+            </div>
+        }
+    }
+
+    fn date_input(ctx: &Context<Self>, errors: &[FieldError], field: &'static str, label: &'static str, end_of_day: bool, value: &Option<String>, msg: fn(Option<String>) -> ChangeMessage) -> Html {
+        html! {
+            <div class="search-input-date">
+                <label for={field} class="search-label">{label}</label>
+                <input type="date" id={field} class="search-input-date-elem"
+                    value={value.as_deref().map(upstream_to_date_input).unwrap_or_default()}
+                    onchange={
+                        ctx.link().callback(move |e: Event| {
+                            let target = e.target().unwrap()
+                                .unchecked_into::<HtmlInputElement>();
+                            msg(date_input_to_upstream(&target.value(), end_of_day))
+                        })
+                    }/>
+                {Self::field_error(errors, field)}
+            </div>
+        }
+    }
+
+    fn select_input(ctx: &Context<Self>, field: &'static str, label: &'static str, options: &[(&'static str, &'static str)], value: &str, msg: fn(String) -> ChangeMessage) -> Html {
+        html! {
+            <div class="search-input-select">
+                <label for={field} class="search-label">{label}</label>
+                <select id={field} class="search-input-select-elem" onchange={
+                    ctx.link().callback(move |e: Event| {
+                        let target = e.target().unwrap()
+                            .unchecked_into::<HtmlSelectElement>();
+                        msg(target.value())
+                    })
+                }>
+                    {
+                        options.iter().map(|(option, name)| html!{
+                            <option value={*option} selected={*option == value}>{*name}</option>
+                        }).collect::<Html>()
+                    }
+                </select>
+            </div>
+        }
+    }
+
+    /// Creators the search is limited to, and a field suggesting more as a name is typed
+    fn creator_input(&self, ctx: &Context<Self>, errors: &[FieldError]) -> Html {
+        let field = if self.request.creator_ids.is_some() { "creatorIds" } else { "creatorId" };
+        let full = self.request.creators().len() >= MAX_CREATORS;
+        html! {
+            <div class="search-input-text search-input-creator">
+                <label for="creator" class="search-label">{"Creators"}</label>
+                <input type="text" id="creator" class="search-input-text-elem"
+                    placeholder={if full { format!("At most {} creators", MAX_CREATORS) } else { "Name or GUID".to_owned() }}
+                    autocomplete="off" disabled={full}
+                    value={self.creator_input.clone()}
+                    oninput={
+                        ctx.link().callback(|e: InputEvent| {
+                            let target = e.target().unwrap()
+                                .unchecked_into::<HtmlInputElement>();
+                            ChangeMessage::CreatorInput(target.value())
+                        })
+                    }
+                    onchange={
+                        // a pasted GUID doesn't need a suggestion
+                        ctx.link().callback(|e: Event| {
+                            let target = e.target().unwrap()
+                                .unchecked_into::<HtmlInputElement>();
+                            let value = target.value().trim().to_owned();
+                            if is_valid_guid(&value) {
+                                ChangeMessage::AddCreator(value, None)
+                            } else {
+                                ChangeMessage::NoOp
+                            }
+                        })
+                    }/>
+                {
+                    if self.creator_suggestions.is_empty() {
+                        html!{}
+                    } else {
+                        html!{
+                            <ul class="search-creator-suggestions">
+                                {
+                                    self.creator_suggestions.iter().map(|creator| {
+                                        let (id, name) = (creator.id.clone(), creator.name.clone());
+                                        html!{
+                                            <li key={creator.id.clone()}
+                                                onclick={ctx.link().callback(move |_| ChangeMessage::AddCreator(id.clone(), Some(name.clone())))}
+                                                title={creator.id.clone()}>
+                                                {&creator.name}
+                                                <span class="search-creator-robots">{format!(" {} robots", creator.robots)}</span>
+                                            </li>
+                                        }
+                                    }).collect::<Html>()
+                                }
+                            </ul>
+                        }
+                    }
+                }
+                <div class="search-creators">
+                    {
+                        self.request.creators().into_iter().map(|id| {
+                            let name = self.creator_names.get(&id).cloned().unwrap_or_else(|| id.clone());
+                            let remove = id.clone();
+                            html!{
+                                <span class="search-creator" key={id.clone()} title={id}>
+                                    {name}
+                                    <button class="search-creator-remove" title="Remove"
+                                        onclick={ctx.link().callback(move |_| ChangeMessage::RemoveCreator(remove.clone()))}>
+                                        {"x"}
+                                    </button>
+                                </span>
+                            }
+                        }).collect::<Html>()
+                    }
+                </div>
+                {Self::field_error(errors, field)}
+            </div>
+        }
+    }
+
+    /// The search in the page URL, if there is one
+    fn url_request(ctx: &Context<Self>) -> Option<SearchRequest> {
+        let location = ctx.link().location()?;
+        if location.search().trim_start_matches('?').is_empty() {
+            return None;
+        }
+        match location.query::<SearchRequest>() {
+            Ok(req) => Some(req),
+            Err(e) => {
+                console::log!("Bad search in URL:", e.to_string());
+                None
+            }
+        }
+    }
+
+    /// Record the search in the URL so it can be shared and revisited with back/forward
+    fn push_url(ctx: &Context<Self>, req: &SearchRequest) {
+        if let Some(history) = ctx.link().history() {
+            if let Err(e) = history.push_with_query(Route::Search, req) {
+                console::log!("Failed to put search in URL:", e.to_string());
+            }
+        }
+    }
+
+    fn send(&mut self, ctx: &Context<Self>, req: SearchRequest) {
+        self.last_request = Some(req.clone());
+        self.error = None;
+        ctx.props().on_search.emit(req.clone());
+        let callback_success = ctx.props().on_results.clone();
+        let callback_failure = ctx.link().callback(ChangeMessage::SearchError);
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = search_query(&req).await;
+            match result {
+                Ok(res) => callback_success.emit(res),
+                Err(e) => callback_failure.emit(e),
+            }
+        });
+    }
 }
 
 impl Component for SearchComponent {
     type Message = ChangeMessage;
     type Properties = SearchProperties;
 
-    fn create(_ctx: &Context<Self>) -> Self {
-        Self {
+    fn create(ctx: &Context<Self>) -> Self {
+        let mut component = Self {
             request: SearchRequest::default(),
+            last_request: None,
             error: None,
+            advanced_open: LocalStorage::get(ADVANCED_OPEN_KEY).unwrap_or(false),
+            creator_input: String::new(),
+            creator_suggestions: Vec::new(),
+            creator_names: HashMap::new(),
+            _history_listener: ctx.link().add_history_listener(ctx.link().callback(|_| ChangeMessage::UrlChanged)),
+        };
+        if let Some(req) = Self::url_request(ctx) {
+            component.request = req.clone();
+            component.send(ctx, req);
         }
+        component
     }
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
@@ -55,77 +340,79 @@ impl Component for SearchComponent {
                 console::log!("Search NoOp");
             },
             ChangeMessage::ClickSearchButton => {
-                let req = self.request.clone();
-                let callback_success = ctx.props().on_results.clone();
-                let callback_failure = ctx.link().callback(|e| ChangeMessage::SearchError(e));
-                wasm_bindgen_futures::spawn_local(async move {
-                    let result = search_query(&req).await;
-                    match result {
-                        Ok(res) => callback_success.emit(res),
-                        Err(e) => callback_failure.emit(e),
-                    }
-                });
+                if !self.request.validate().is_empty() {
+                    console::log!("Search is invalid, not sending");
+                    return false;
+                }
+                Self::push_url(ctx, &self.request);
+                self.send(ctx, self.request.clone());
                 console::log!("Click search button");
             },
             ChangeMessage::ClickErrorX => {
                 self.error = None;
                 console::log!("Click error X");
             },
+            ChangeMessage::ClickRetry => {
+                if let Some(req) = self.last_request.clone() {
+                    self.send(ctx, req);
+                }
+                console::log!("Click retry");
+            },
             ChangeMessage::SearchError(e) => {
-                console::log!("Search error:", &e);
+                console::log!("Search error:", e.details());
                 self.error = Some(e);
             },
             ChangeMessage::SetText(text) => {
-                console::log!("Search text:", &text);
-                self.request.text = Some(text);
+                console::log!("Search text:", format!("{:?}", text));
+                self.request.text = text;
             },
             ChangeMessage::SetBaseMinimumCpu(cpu) => {
-                console::log!("Search base min cpu:", cpu);
-                self.request.base_minimum_cpu = Some(cpu);
+                console::log!("Search base min cpu:", format!("{:?}", cpu));
+                self.request.base_minimum_cpu = cpu;
             },
             ChangeMessage::SetBaseMaximumCpu(cpu) => {
-                console::log!("Search base max cpu:", cpu);
-                self.request.base_maximum_cpu = Some(cpu);
+                console::log!("Search base max cpu:", format!("{:?}", cpu));
+                self.request.base_maximum_cpu = cpu;
             },
             ChangeMessage::SetWeaponMinimumCpu(cpu) => {
-                console::log!("Search weapon min cpu:", cpu);
-                self.request.weapon_minimum_cpu = Some(cpu);
+                console::log!("Search weapon min cpu:", format!("{:?}", cpu));
+                self.request.weapon_minimum_cpu = cpu;
             },
             ChangeMessage::SetWeaponMaximumCpu(cpu) => {
-                console::log!("Search weapon max cpu:", cpu);
-                self.request.weapon_maximum_cpu = Some(cpu);
+                console::log!("Search weapon max cpu:", format!("{:?}", cpu));
+                self.request.weapon_maximum_cpu = cpu;
             },
             ChangeMessage::SetCosmeticMinimumCpu(cpu) => {
-                console::log!("Search cosmetic min cpu:", cpu);
-                self.request.cosmetic_minimum_cpu = Some(cpu);
+                console::log!("Search cosmetic min cpu:", format!("{:?}", cpu));
+                self.request.cosmetic_minimum_cpu = cpu;
             },
             ChangeMessage::SetCosmeticMaximumCpu(cpu) => {
-                console::log!("Search cosmetic max cpu:", cpu);
-                self.request.cosmetic_maximum_cpu = Some(cpu);
+                console::log!("Search cosmetic max cpu:", format!("{:?}", cpu));
+                self.request.cosmetic_maximum_cpu = cpu;
             },
             ChangeMessage::SetClusterMinimum(clusters) => {
-                console::log!("Search clusters min:", clusters);
-                self.request.cluster_minimum = Some(clusters);
+                console::log!("Search clusters min:", format!("{:?}", clusters));
+                self.request.cluster_minimum = clusters;
             },
             ChangeMessage::SetClusterMaximum(clusters) => {
-                console::log!("Search clusters max:", clusters);
-                self.request.cluster_maximum = Some(clusters);
+                console::log!("Search clusters max:", format!("{:?}", clusters));
+                self.request.cluster_maximum = clusters;
             },
             ChangeMessage::SetDateMinimum(date) => {
-                console::log!("Search date minimum:", &date);
-                self.request.date_minimum = Some(date);
+                console::log!("Search date minimum:", format!("{:?}", date));
+                self.request.date_minimum = date;
             },
             ChangeMessage::SetDateMaximum(date) => {
-                console::log!("Search date max:", &date);
-                self.request.date_maximum = Some(date);
+                console::log!("Search date max:", format!("{:?}", date));
+                self.request.date_maximum = date;
             },
             ChangeMessage::SetPage(page) => {
-                console::log!("Search page:", page);
-                self.request.page = Some(page);
+                console::log!("Search page:", format!("{:?}", page));
+                self.request.page = page;
             },
             ChangeMessage::SetCount(count) => {
-                console::log!("Search count:", count);
-                self.request.count = Some(count);
+                console::log!("Search count:", format!("{:?}", count));
+                self.request.count = count;
             },
             ChangeMessage::SetSortBy(sort) => {
                 console::log!("Search sort:", &sort);
@@ -135,175 +422,217 @@ impl Component for SearchComponent {
                 console::log!("Search order:", &order);
                 self.request.order_by = order;
             },
+            ChangeMessage::SetPriceMinimum(price) => {
+                console::log!("Search price min:", format!("{:?}", price));
+                self.request.price_minimum = price;
+            },
+            ChangeMessage::SetPriceMaximum(price) => {
+                console::log!("Search price max:", format!("{:?}", price));
+                self.request.price_maximum = price;
+            },
+            ChangeMessage::SetPriceCurrency(currency) => {
+                console::log!("Search price currency:", &currency);
+                self.request.price_currency = currency.parse().ok();
+            },
+            ChangeMessage::SetBlocksIncluded(blocks) => {
+                console::log!("Search blocks included:", format!("{:?}", blocks));
+                self.request.blocks_included = blocks;
+            },
+            ChangeMessage::SetBlocksExcluded(blocks) => {
+                console::log!("Search blocks excluded:", format!("{:?}", blocks));
+                self.request.blocks_excluded = blocks;
+            },
+            ChangeMessage::SetMaterialsIncluded(materials) => {
+                console::log!("Search materials included:", format!("{:?}", materials));
+                self.request.materials_included = materials;
+            },
+            ChangeMessage::SetMaterialsExcluded(materials) => {
+                console::log!("Search materials excluded:", format!("{:?}", materials));
+                self.request.materials_excluded = materials;
+            },
+            ChangeMessage::SetFullText(fulltext) => {
+                console::log!("Search full-text:", fulltext);
+                self.request.mode = if fulltext { Some("fulltext".to_owned()) } else { None };
+            },
+            ChangeMessage::CreatorInput(text) => {
+                self.creator_input = text.clone();
+                if text.trim().is_empty() {
+                    self.creator_suggestions.clear();
+                    return true;
+                }
+                let callback = ctx.link().callback(|(text, result)| ChangeMessage::CreatorSuggestions(text, result));
+                wasm_bindgen_futures::spawn_local(async move {
+                    let result = creators_query(text.trim()).await;
+                    callback.emit((text, result));
+                });
+                return false;
+            },
+            ChangeMessage::CreatorSuggestions(text, result) => {
+                // only the answer for what's typed now
+                if text != self.creator_input {
+                    return false;
+                }
+                match result {
+                    Ok(suggestions) => self.creator_suggestions = suggestions,
+                    Err(e) => {
+                        console::log!("Creator suggestions failed:", e.details());
+                        self.creator_suggestions.clear();
+                    },
+                }
+            },
+            ChangeMessage::AddCreator(id, name) => {
+                console::log!("Search add creator:", &id);
+                if let Some(name) = name {
+                    self.creator_names.insert(id.clone(), name);
+                }
+                let mut creators = self.request.creators();
+                if !creators.iter().any(|c| c.eq_ignore_ascii_case(&id)) {
+                    creators.push(id);
+                }
+                self.request.set_creators(&creators);
+                self.creator_input.clear();
+                self.creator_suggestions.clear();
+            },
+            ChangeMessage::RemoveCreator(id) => {
+                console::log!("Search remove creator:", &id);
+                let creators: Vec<String> = self.request.creators().into_iter().filter(|c| *c != id).collect();
+                self.request.set_creators(&creators);
+            },
+            ChangeMessage::RunSaved(req) => {
+                self.request = req;
+                self.creator_input.clear();
+                self.creator_suggestions.clear();
+                if !self.request.validate().is_empty() {
+                    console::log!("Saved search is invalid, not sending");
+                    return true;
+                }
+                Self::push_url(ctx, &self.request);
+                self.send(ctx, self.request.clone());
+            },
+            ChangeMessage::ToggleAdvanced => {
+                self.advanced_open = !self.advanced_open;
+                if let Err(e) = LocalStorage::set(ADVANCED_OPEN_KEY, self.advanced_open) {
+                    console::log!("Failed to remember advanced filters state:", e.to_string());
+                }
+            },
+            ChangeMessage::UrlChanged => {
+                // fired by our own push_url too, which already sent the search
+                match Self::url_request(ctx) {
+                    Some(req) if self.last_request.as_ref() != Some(&req) => {
+                        console::log!("Search from URL");
+                        self.request = req.clone();
+                        self.send(ctx, req);
+                    },
+                    Some(_) => return false,
+                    None => {
+                        if self.last_request.is_none() {
+                            return false;
+                        }
+                        console::log!("Search cleared by URL");
+                        self.request = SearchRequest::default();
+                        self.last_request = None;
+                        self.error = None;
+                        ctx.props().on_results.emit(SearchResults::default());
+                    },
+                }
+            },
             ChangeMessage::Clear => {
                 console::log!("Search clear");
                 self.request = SearchRequest::default();
+                self.error = None;
+                self.creator_input.clear();
+                self.creator_suggestions.clear();
             }
         }
         true
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
+        let errors = self.request.validate();
+        let req = &self.request;
         html! {
             <div class="search">
                 <div class="search-input-text">
-                    <label for="search_text" class="search-label">{"Text"}</label>
-                    <input type="text" id="search_text" class="search-input-text-elem" onchange={
-                        ctx.link().callback(|e: Event| {
-                            let target = e.target().unwrap()
-                                .unchecked_into::<HtmlInputElement>();
-                            ChangeMessage::SetText(target.value())
-                        })
-                    }/>
-                </div>
-                <div class="search-input-number">
-                    <label for="base_min_cpu" class="search-label">{"Minimum Base CPU"}</label>
-                    <input type="number" id="base_min_cpu" class="search-input-number-elem" min="1" onchange={
-                        ctx.link().batch_callback(|e: Event| {
-                            let target = e.target().unwrap()
-                                .unchecked_into::<HtmlInputElement>();
-                            //console::log!("Range", target.value());
-                            match target.value().parse() {
-                                Ok(val) => Some(ChangeMessage::SetBaseMinimumCpu(val)),
-                                Err(e) => {
-                                    console::log!("base_min_cpu parse err:", e.to_string());
-                                    None
-                                }
-                            }
-                        })
-                    }/>
-                </div>
-                <div class="search-input-number">
-                    <label for="base_max_cpu" class="search-label">{"Maximum Base CPU"}</label>
-                    <input type="number" id="base_max_cpu" class="search-input-number-elem" min="1" onchange={
-                        ctx.link().batch_callback(|e: Event| {
-                            let target = e.target().unwrap()
-                                .unchecked_into::<HtmlInputElement>();
-                            //console::log!("Range", target.value());
-                            match target.value().parse() {
-                                Ok(val) => Some(ChangeMessage::SetBaseMaximumCpu(val)),
-                                Err(e) => {
-                                    console::log!("base_max_cpu parse err:", e.to_string());
-                                    None
-                                }
-                            }
-                        })
-                    }/>
-                </div>
-                <div class="search-input-number">
-                    <label for="cluster_min" class="search-label">{"Minimum Clusters"}</label>
-                    <input type="number" id="cluster_min" class="search-input-number-elem" min="1" onchange={
-                        ctx.link().batch_callback(|e: Event| {
-                            let target = e.target().unwrap()
-                                .unchecked_into::<HtmlInputElement>();
-                            //console::log!("Range", target.value());
-                            match target.value().parse() {
-                                Ok(val) => Some(ChangeMessage::SetClusterMinimum(val)),
-                                Err(e) => {
-                                    console::log!("cluster_min parse err:", e.to_string());
-                                    None
-                                }
-                            }
-                        })
-                    }/>
-                </div>
-                <div class="search-input-number">
-                    <label for="cluster_max" class="search-label">{"Maximum Clusters"}</label>
-                    <input type="number" id="cluster_max" class="search-input-number-elem" min="1" onchange={
-                        ctx.link().batch_callback(|e: Event| {
-                            let target = e.target().unwrap()
-                                .unchecked_into::<HtmlInputElement>();
-                            //console::log!("Range", target.value());
-                            match target.value().parse() {
-                                Ok(val) => Some(ChangeMessage::SetClusterMaximum(val)),
-                                Err(e) => {
-                                    console::log!("cluster_max parse err:", e.to_string());
-                                    None
-                                }
-                            }
-                        })
-                    }/>
-                </div>
-                <div class="search-input-number">
-                    <label for="page" class="search-label">{"Page"}</label>
-                    <input type="number" id="page" class="search-input-number-elem" min="1" onchange={
-                        ctx.link().batch_callback(|e: Event| {
-                            let target = e.target().unwrap()
-                                .unchecked_into::<HtmlInputElement>();
-                            //console::log!("Range", target.value());
-                            match target.value().parse() {
-                                Ok(val) => Some(ChangeMessage::SetPage(val)),
-                                Err(e) => {
-                                    console::log!("page parse err:", e.to_string());
-                                    None
-                                }
-                            }
-                        })
-                    }/>
-                </div>
-                <div class="search-input-number">
-                    <label for="count" class="search-label">{"Results"}</label>
-                    <input type="number" id="count" class="search-input-number-elem" min="1" onchange={
-                        ctx.link().batch_callback(|e: Event| {
-                            let target = e.target().unwrap()
-                                .unchecked_into::<HtmlInputElement>();
-                            //console::log!("Range", target.value());
-                            match target.value().parse() {
-                                Ok(val) => Some(ChangeMessage::SetCount(val)),
-                                Err(e) => {
-                                    console::log!("count parse err:", e.to_string());
-                                    None
-                                }
-                            }
-                        })
-                    }/>
+                    <label for="text" class="search-label">{"Text"}</label>
+                    <input type="text" id="text" class="search-input-text-elem"
+                        value={req.text.clone().unwrap_or_default()}
+                        onchange={
+                            ctx.link().callback(|e: Event| {
+                                let target = e.target().unwrap()
+                                    .unchecked_into::<HtmlInputElement>();
+                                let value = target.value();
+                                ChangeMessage::SetText(if value.trim().is_empty() { None } else { Some(value) })
+                            })
+                        }/>
+                    {Self::field_error(&errors, "text")}
                 </div>
-                <div class="search-input-select">
-                    <label for="sort_by" class="search-label">{"Sort"}</label>
-                    <select id="sort_by" class="search-input-select-elem" onchange={
-                        ctx.link().callback(|e: Event| {
-                            let target = e.target().unwrap()
-                                .unchecked_into::<HtmlInputElement>();
-                            //console::log!("Range", target.value());
-                            ChangeMessage::SetSortBy(target.value())
-                        })
-                    }>
-                        <option value="default" selected=true>{"Default"}</option>
-                        <option value="cpuPower">{"CPU"}</option>
-                        <option value="cpuWeapon">{"Weapon CPU"}</option>
-                        <option value="cpuCosmetic">{"Cosmetic CPU"}</option>
-                        <option value="price">{"Price"}</option>
-                        <option value="date">{"Date"}</option>
-                        <option value="clusterCount">{"Clusters"}</option>
-                        <option value="views">{"Views"}</option>
-                    </select>
+                <div class="search-input-checkbox">
+                    <input type="checkbox" id="mode" class="search-input-checkbox-elem"
+                        checked={req.is_fulltext()}
+                        title="Match robot and creator names, allowing typos, from robots crf_2b has seen"
+                        onchange={
+                            ctx.link().callback(|e: Event| {
+                                let target = e.target().unwrap()
+                                    .unchecked_into::<HtmlInputElement>();
+                                ChangeMessage::SetFullText(target.checked())
+                            })
+                        }/>
+                    <label for="mode" class="search-label">{"Full-text"}</label>
                 </div>
-                <div class="search-input-select">
-                    <label for="order_by" class="search-label">{"Order"}</label>
-                    <select id="order_by" class="search-input-select-elem" onchange={
-                        ctx.link().callback(|e: Event| {
-                            let target = e.target().unwrap()
-                                .unchecked_into::<HtmlInputElement>();
-                            //console::log!("Range", target.value());
-                            ChangeMessage::SetOrderBy(target.value())
-                        })
-                    }>
-                        <option value="ascending" selected=true>{"Ascending"}</option>
-                        <option value="descending">{"Descending"}</option>
-                    </select>
+                {Self::number_input(ctx, &errors, "baseCpuMinimum", "Minimum Base CPU", "0", req.base_minimum_cpu, ChangeMessage::SetBaseMinimumCpu)}
+                {Self::number_input(ctx, &errors, "baseCpuMaximum", "Maximum Base CPU", "0", req.base_maximum_cpu, ChangeMessage::SetBaseMaximumCpu)}
+                {Self::number_input(ctx, &errors, "clusterMinimum", "Minimum Clusters", "0", req.cluster_minimum, ChangeMessage::SetClusterMinimum)}
+                {Self::number_input(ctx, &errors, "clusterMaximum", "Maximum Clusters", "0", req.cluster_maximum, ChangeMessage::SetClusterMaximum)}
+                {Self::number_input(ctx, &errors, "page", "Page", "1", req.page, ChangeMessage::SetPage)}
+                {Self::number_input(ctx, &errors, "count", "Results", "1", req.count, ChangeMessage::SetCount)}
+                {Self::select_input(ctx, "sortBy", "Sort", SORT_NAMES, &req.sort_by, ChangeMessage::SetSortBy)}
+                {Self::select_input(ctx, "orderBy", "Order", ORDER_NAMES, &req.order_by, ChangeMessage::SetOrderBy)}
+                <div class="search-advanced">
+                    <button class="search-advanced-toggle-elem" onclick={ctx.link().callback(|_| ChangeMessage::ToggleAdvanced)}>
+                        { if self.advanced_open { "Hide advanced filters" } else { "Advanced filters" } }
+                    </button>
+                    <div class="search-advanced-panel" hidden={!self.advanced_open}>
+                        {Self::number_input(ctx, &errors, "weaponCpuMinimum", "Minimum Weapon CPU", "0", req.weapon_minimum_cpu, ChangeMessage::SetWeaponMinimumCpu)}
+                        {Self::number_input(ctx, &errors, "weaponCpuMaximum", "Maximum Weapon CPU", "0", req.weapon_maximum_cpu, ChangeMessage::SetWeaponMaximumCpu)}
+                        {Self::number_input(ctx, &errors, "cosmeticCpuMinimum", "Minimum Cosmetic CPU", "0", req.cosmetic_minimum_cpu, ChangeMessage::SetCosmeticMinimumCpu)}
+                        {Self::number_input(ctx, &errors, "cosmeticCpuMaximum", "Maximum Cosmetic CPU", "0", req.cosmetic_maximum_cpu, ChangeMessage::SetCosmeticMaximumCpu)}
+                        {Self::date_input(ctx, &errors, "dateMinimum", "Uploaded after", false, &req.date_minimum, ChangeMessage::SetDateMinimum)}
+                        {Self::date_input(ctx, &errors, "dateMaximum", "Uploaded before", true, &req.date_maximum, ChangeMessage::SetDateMaximum)}
+                        {self.creator_input(ctx, &errors)}
+                        {Self::number_input(ctx, &errors, "priceMinimum", "Minimum Price", "0", req.price_minimum, ChangeMessage::SetPriceMinimum)}
+                        {Self::number_input(ctx, &errors, "priceMaximum", "Maximum Price", "0", req.price_maximum, ChangeMessage::SetPriceMaximum)}
+                        {Self::select_input(ctx, "priceCurrency", "Price in", CURRENCY_NAMES, &req.price_currency.map(|c| c.to_string()).unwrap_or_default(), ChangeMessage::SetPriceCurrency)}
+                        {Self::text_input(ctx, &errors, "blocksIncluded", "Blocks used (ID:at least)", "345:10, 200", &req.blocks_included, ChangeMessage::SetBlocksIncluded)}
+                        {Self::text_input(ctx, &errors, "blocksExcluded", "Blocks not used (ID:fewer than)", "17, 18:5", &req.blocks_excluded, ChangeMessage::SetBlocksExcluded)}
+                        {Self::text_input(ctx, &errors, "materialsIncluded", "Materials used", "12, 3", &req.materials_included, ChangeMessage::SetMaterialsIncluded)}
+                        {Self::text_input(ctx, &errors, "materialsExcluded", "Materials not used", "4", &req.materials_excluded, ChangeMessage::SetMaterialsExcluded)}
+                    </div>
                 </div>
+                {
+                    if let Some(e) = &self.error {
+                        html!{
+                            <ErrorBannerComponent
+                                error={e.clone()}
+                                on_dismiss={ctx.link().callback(|_| ChangeMessage::ClickErrorX)}
+                                on_retry={ctx.link().callback(|_| ChangeMessage::ClickRetry)}/>
+                        }
+                    } else {
+                        html!{}
+                    }
+                }
                 <div class="search-button-drawer">
                     <div class="search-input-button" align="center">
-                        <button class="search-input-button-elem" onclick={ctx.link().callback(|_| ChangeMessage::ClickSearchButton)}>
+                        <button class="search-input-button-elem" disabled={!errors.is_empty()} onclick={ctx.link().callback(|_| ChangeMessage::ClickSearchButton)}>
                             {"Search"}
                         </button>
                     </div>
+                    <div class="search-input-button" align="center">
+                        <button class="search-clear-button-elem" onclick={ctx.link().callback(|_| ChangeMessage::Clear)}>
+                            {"Reset"}
+                        </button>
+                    </div>
                 </div>
-                /*<div class="search-input-button" align="center">
-                    <button class="search-clear-button-elem" onclick={ctx.link().callback(|_| ChangeMessage::ClickSearchButton)}>
-                        {"Reset"}
-                    </button>
-                </div>*/
+                <SavedSearchesComponent current={self.request.clone()} on_run={ctx.link().callback(ChangeMessage::RunSaved)}/>
             </div>
         }
     }
@@ -0,0 +1,79 @@
+use wasm_bindgen::JsValue;
+use yew::{html, Component, Context, Html, Properties};
+
+const WIDTH: f64 = 120.0;
+const HEIGHT: f64 = 30.0;
+
+#[derive(Properties, PartialEq)]
+pub struct SparklineProperties {
+    pub label: String,
+    /// (unix seconds, value), oldest first
+    pub points: Vec<(i64, f64)>,
+}
+
+/// A small line chart of one value over time, with its latest value
+pub struct SparklineComponent;
+
+impl SparklineComponent {
+    fn date(secs: i64) -> String {
+        let date = js_sys::Date::new(&JsValue::from_f64(secs as f64 * 1000.0));
+        String::from(date.to_date_string())
+    }
+
+    /// Times the value changed. Points are recorded when any of a robot's values change,
+    /// so neighbouring points can hold the same value for this one.
+    fn changes(points: &[(i64, f64)]) -> usize {
+        points.windows(2).filter(|pair| pair[0].1 != pair[1].1).count()
+    }
+
+    /// SVG polyline points scaled to fit the chart; a flat line sits in the middle
+    fn polyline(points: &[(i64, f64)]) -> String {
+        let (first, last) = (points[0].0, points[points.len() - 1].0);
+        let min = points.iter().map(|(_, v)| *v).fold(f64::INFINITY, f64::min);
+        let max = points.iter().map(|(_, v)| *v).fold(f64::NEG_INFINITY, f64::max);
+        points.iter()
+            .map(|(t, v)| {
+                let x = if last == first { WIDTH } else { (t - first) as f64 / (last - first) as f64 * WIDTH };
+                let y = if max == min { HEIGHT / 2.0 } else { HEIGHT - (v - min) / (max - min) * HEIGHT };
+                format!("{:.1},{:.1}", x, y)
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl Component for SparklineComponent {
+    type Message = ();
+    type Properties = SparklineProperties;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self
+    }
+
+    fn update(&mut self, _ctx: &Context<Self>, _msg: Self::Message) -> bool {
+        false
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let props = ctx.props();
+        let (since, latest) = match (props.points.first(), props.points.last()) {
+            (Some(first), Some(last)) => (first.0, last.1),
+            _ => return html!{},
+        };
+        let title = format!("{} since {}: {} change(s)", props.label, Self::date(since), Self::changes(&props.points));
+        // a single point still gets a (flat) line
+        let mut points = props.points.clone();
+        if points.len() == 1 {
+            points.insert(0, (since - 1, latest));
+        }
+        html! {
+            <div class="sparkline" title={title}>
+                <span class="sparkline-label">{&props.label}</span>
+                <svg class="sparkline-chart" viewBox={format!("-1 -1 {} {}", WIDTH + 2.0, HEIGHT + 2.0)} preserveAspectRatio="none">
+                    <polyline points={Self::polyline(&points)} fill="none" stroke="currentColor" stroke-width="1.5"/>
+                </svg>
+                <span class="sparkline-value">{latest}</span>
+            </div>
+        }
+    }
+}
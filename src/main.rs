@@ -1,5 +1,6 @@
 mod api;
 mod components;
+mod route;
 
 fn main() {
     yew::start_app::<components::RootComponent>();
@@ -0,0 +1,16 @@
+use yew_router::Routable;
+
+#[derive(Routable, Clone, PartialEq, Debug)]
+pub enum Route {
+    /// Search form and results; the search itself lives in the query string
+    #[at("/")]
+    Search,
+    #[at("/robot/:id")]
+    Robot { id: String },
+    /// Everything by one creator, by creator GUID
+    #[at("/creator/:id")]
+    Creator { id: String },
+    #[not_found]
+    #[at("/404")]
+    NotFound,
+}